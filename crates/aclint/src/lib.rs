@@ -1,9 +1,174 @@
 //! Rust support for RISC-V ACLINT (Advanced Core Local Interruptor) peripheral.
 //!
 //! RISC-V ACLINT is defined in <https://github.com/riscv/riscv-aclint>.
+//!
+//! # Features
+//!
+//! - `inline-always`: mark the register accessors `#[inline(always)]` instead of `#[inline]`,
+//!   so that interrupt handlers never pay for a call. Every call site then carries its own copy
+//!   of the access, which grows code size; leave it off unless the hot path needs it.
+//! - `trace-ring`: keep the last [`TRACE_RING_LEN`] register accesses in [`TRACE_RING`],
+//!   so that a panic handler can dump what the code last did to the device.
+//! - `duration`: [`SifiveClint::time_until`] to express deadlines as [`core::time::Duration`].
+//! - `riscv`: [`SifiveClint::my_pending`], which finds the current hart through `mhartid`,
+//!   and [`SifiveClint::arm_timer_guarded`], which enables `mie.MTIE` for a scoped timer.
 #![no_std]
 
 use core::cell::UnsafeCell;
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::sync::atomic::{Ordering, fence};
+#[cfg(feature = "duration")]
+use core::time::Duration;
+
+#[cfg(feature = "trace-ring")]
+mod trace;
+#[cfg(feature = "trace-ring")]
+pub use trace::{MmioAccess, TRACE_RING, TRACE_RING_LEN, TraceRing};
+
+/// Volatile read of a register, recorded in the trace ring if enabled.
+///
+/// # Safety
+///
+/// `reg` must be a register of a mapped device.
+#[inline(always)]
+unsafe fn load<T: Copy + Into<u64>>(reg: &UnsafeCell<T>) -> T {
+    let val = unsafe { reg.get().read_volatile() };
+    #[cfg(feature = "trace-ring")]
+    trace::record(reg.get() as usize, val.into(), false);
+    val
+}
+
+/// Volatile write of a register, recorded in the trace ring if enabled.
+///
+/// # Safety
+///
+/// `reg` must be a register of a mapped device.
+#[inline(always)]
+unsafe fn store<T: Copy + Into<u64>>(reg: &UnsafeCell<T>, val: T) {
+    #[cfg(feature = "trace-ring")]
+    trace::record(reg.get() as usize, val.into(), true);
+    unsafe { reg.get().write_volatile(val) }
+}
+
+/// Read a 64-bit counter or compare register.
+///
+/// RV32 harts read it as two words, high word first, and retry if the high word changed
+/// in between, so that a carry out of the low word is never torn.
+///
+/// # Safety
+///
+/// `reg` must be a register of a mapped device.
+#[inline(always)]
+unsafe fn load_u64(reg: &UnsafeCell<u64>) -> u64 {
+    #[cfg(target_pointer_width = "64")]
+    {
+        unsafe { load(reg) }
+    }
+    #[cfg(target_pointer_width = "32")]
+    {
+        let (lo, hi) = halves(reg);
+        read_split(|| unsafe { load(hi) }, || unsafe { load(lo) })
+    }
+}
+
+/// Write a 64-bit `MTIMECMP` register.
+///
+/// RV32 harts first park the low word at `0xffff_ffff`, so that no intermediate value
+/// can fire the comparator, then write the high word and the real low word.
+///
+/// # Safety
+///
+/// `reg` must be a register of a mapped device.
+#[inline(always)]
+unsafe fn store_mtimecmp(reg: &UnsafeCell<u64>, val: u64) {
+    #[cfg(target_pointer_width = "64")]
+    unsafe {
+        store(reg, val)
+    }
+    #[cfg(target_pointer_width = "32")]
+    {
+        let (lo, hi) = halves(reg);
+        write_split(
+            |v| unsafe { store(lo, v) },
+            |v| unsafe { store(hi, v) },
+            val,
+        )
+    }
+}
+
+/// Low and high words of a little-endian 64-bit register.
+#[cfg(target_pointer_width = "32")]
+#[inline(always)]
+fn halves(reg: &UnsafeCell<u64>) -> (&UnsafeCell<u32>, &UnsafeCell<u32>) {
+    let lo = (reg as *const UnsafeCell<u64>).cast::<UnsafeCell<u32>>();
+    unsafe { (&*lo, &*lo.add(1)) }
+}
+
+/// Read-high, read-low, re-read-high until the high word is stable.
+#[cfg(any(target_pointer_width = "32", test))]
+#[inline(always)]
+fn read_split(mut read_hi: impl FnMut() -> u32, mut read_lo: impl FnMut() -> u32) -> u64 {
+    loop {
+        let hi = read_hi();
+        let lo = read_lo();
+        if read_hi() == hi {
+            return (hi as u64) << 32 | lo as u64;
+        }
+    }
+}
+
+/// Write low word `0xffff_ffff`, then the high word, then the low word of `val`.
+#[cfg(any(target_pointer_width = "32", test))]
+#[inline(always)]
+fn write_split(mut write_lo: impl FnMut(u32), mut write_hi: impl FnMut(u32), val: u64) {
+    write_lo(u32::MAX);
+    write_hi((val >> 32) as u32);
+    write_lo(val as u32);
+}
+
+/// Default bound for the busy-wait helpers, such as [`SifiveClint::wait_msip`].
+///
+/// It counts polling iterations, not time; how long it lasts depends on the hart and
+/// on how fast the MMIO reads are. Pass a larger bound on slow hardware, or a smaller
+/// one on fast emulators.
+pub const DEFAULT_MAX_SPINS: usize = 1_000_000;
+
+/// Offset of the `MSIP` registers in a SiFive CLINT region.
+pub const MSIP_OFFSET: usize = 0x0;
+/// Offset of the `MTIMECMP` registers in a SiFive CLINT region.
+pub const MTIMECMP_OFFSET: usize = 0x4000;
+/// Offset of the `MTIME` register in a SiFive CLINT region.
+pub const MTIME_OFFSET: usize = 0xbff8;
+
+/// Size of the MMIO region of an MSWI device.
+pub const MSWI_SIZE: usize = 0x4000;
+/// Size of the MMIO region of an SSWI device.
+pub const SSWI_SIZE: usize = 0x4000;
+/// Size of the MMIO region of an MTIMER device.
+pub const MTIMER_SIZE: usize = 0x8000;
+/// Size of the MMIO region of a SiFive CLINT.
+pub const SIFIVE_CLINT_SIZE: usize = 0xc000;
+
+/// Address of the `MSIP` register of given hart in the SiFive CLINT at `clint_base`.
+#[inline]
+pub const fn msip_addr(clint_base: usize, hart_idx: usize) -> usize {
+    debug_assert!(hart_idx < 4095);
+    clint_base + MSIP_OFFSET + hart_idx * 4
+}
+
+/// Address of the `MTIMECMP` register of given hart in the SiFive CLINT at `clint_base`.
+#[inline]
+pub const fn mtimecmp_addr(clint_base: usize, hart_idx: usize) -> usize {
+    debug_assert!(hart_idx < 4095);
+    clint_base + MTIMECMP_OFFSET + hart_idx * 8
+}
+
+/// Address of the `MTIME` register in the SiFive CLINT at `clint_base`.
+#[inline]
+pub const fn mtime_addr(clint_base: usize) -> usize {
+    clint_base + MTIME_OFFSET
+}
 
 /// Machine-level time counter register.
 #[repr(transparent)]
@@ -21,7 +186,34 @@ pub struct MSIP(UnsafeCell<u32>);
 #[repr(transparent)]
 pub struct SETSSIP(UnsafeCell<u32>);
 
-/// Machine-level Software Interrupt Device (MSWI).
+/// Most harts a device can cover, and the default of the `HARTS` parameter through the
+/// [`MSWI`], [`SSWI`], [`MTIMER`] and [`SifiveClint`] aliases.
+pub const MAX_HARTS: usize = 4095;
+
+/// `T` followed by padding up to `SIZE` bytes, so that the next register block stays at its
+/// fixed offset however many harts `T` covers.
+///
+/// Dereferences to `T`. If `T` is larger than `SIZE`, the block simply takes `T`'s size.
+#[repr(C)]
+pub union Padded<T, const SIZE: usize> {
+    value: ManuallyDrop<T>,
+    _size: [u8; SIZE],
+}
+
+impl<T, const SIZE: usize> Deref for Padded<T, SIZE> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &self.value }
+    }
+}
+
+/// Machine-level Software Interrupt Device (MSWI) covering `HARTS` harts, at most [`MAX_HARTS`].
+///
+/// Only the `MSIP` registers and the reserved word after the last of them are included;
+/// the rest of the [`MSWI_SIZE`] window is reserved, so a device for a few harts needs no more
+/// than a few words mapped. With [`MAX_HARTS`] harts this spans the whole window.
 ///
 /// # Usage
 ///
@@ -40,78 +232,1878 @@ pub struct SETSSIP(UnsafeCell<u32>);
 /// }
 /// ```
 #[repr(C)]
-pub struct MSWI {
-    /// HART index 0..4095 machine-level IPI registers.
-    pub msip: [MSIP; 4095],
+pub struct Mswi<const HARTS: usize> {
+    /// HART index 0..HARTS machine-level IPI registers.
+    pub msip: [MSIP; HARTS],
     _reserved: u32,
 }
 
-/// Supervisor-level Software Interrupt Device (SSWI).
+/// MSWI device covering all [`MAX_HARTS`] harts.
+pub type MSWI = Mswi<MAX_HARTS>;
+
+impl<const HARTS: usize> Mswi<HARTS> {
+    /// Refer to the MSWI device at `base`, as found in its own device tree node.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the address of a mapped MSWI device that outlives the returned reference.
+    #[inline]
+    pub const unsafe fn from_base(base: usize) -> &'static Self {
+        const { assert!(HARTS <= MAX_HARTS) };
+        unsafe { &*(base as *const Self) }
+    }
+
+    /// Read machine-level software interrupt state for given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn read_msip(&self, hart_idx: usize) -> bool {
+        unsafe { load(&self.msip[hart_idx].0) != 0 }
+    }
+
+    /// Set machine-level software interrupt for given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn set_msip(&self, hart_idx: usize) {
+        unsafe { store(&self.msip[hart_idx].0, 1) }
+    }
+
+    /// Clear machine-level software interrupt for given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn clear_msip(&self, hart_idx: usize) {
+        unsafe { store(&self.msip[hart_idx].0, 0) }
+    }
+
+    /// Read machine-level software interrupt state for given hart, checking the index.
+    #[inline]
+    pub fn try_read_msip(&self, hart_idx: usize) -> Result<bool, InvalidHartId> {
+        Ok(unsafe { load(&self.checked_msip(hart_idx)?.0) != 0 })
+    }
+
+    /// Set machine-level software interrupt for given hart, checking the index.
+    #[inline]
+    pub fn try_set_msip(&self, hart_idx: usize) -> Result<(), InvalidHartId> {
+        unsafe { store(&self.checked_msip(hart_idx)?.0, 1) };
+        Ok(())
+    }
+
+    /// Clear machine-level software interrupt for given hart, checking the index.
+    #[inline]
+    pub fn try_clear_msip(&self, hart_idx: usize) -> Result<(), InvalidHartId> {
+        unsafe { store(&self.checked_msip(hart_idx)?.0, 0) };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn checked_msip(&self, hart_idx: usize) -> Result<&MSIP, InvalidHartId> {
+        self.msip.get(hart_idx).ok_or(InvalidHartId(hart_idx))
+    }
+
+    /// Count harts among the first `hart_count` (at most `HARTS`) with software interrupt pending.
+    #[inline]
+    pub fn pending_count(&self, hart_count: usize) -> usize {
+        (0..hart_count.min(self.msip.len()))
+            .filter(|&i| self.read_msip(i))
+            .count()
+    }
+
+    /// Set or clear `MSIP` of each hart according to `states[hart_idx]`.
+    ///
+    /// Entries beyond the last of the `HARTS` harts are ignored.
+    #[inline]
+    pub fn apply_states(&self, states: &[bool]) {
+        for (hart_idx, &pending) in states.iter().take(self.msip.len()).enumerate() {
+            if pending {
+                self.set_msip(hart_idx);
+            } else {
+                self.clear_msip(hart_idx);
+            }
+        }
+    }
+}
+
+/// Machine-level Timer Device (MTIMER) covering `HARTS` harts, at most [`MAX_HARTS`].
+///
+/// `MTIME` sits at the end of the [`MTIMER_SIZE`] window whatever `HARTS` is.
+#[repr(C)]
+pub struct Mtimer<const HARTS: usize> {
+    /// HART index 0..HARTS machine-level time compare registers.
+    pub mtimecmp: Padded<[MTIMECMP; HARTS], 0x7ff8>,
+    /// Machine-level time counter register.
+    pub mtime: MTIME,
+}
+
+/// MTIMER device covering all [`MAX_HARTS`] harts.
+pub type MTIMER = Mtimer<MAX_HARTS>;
+
+impl<const HARTS: usize> Mtimer<HARTS> {
+    /// Refer to the MTIMER device at `base`, as found in its own device tree node.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the address of a mapped MTIMER device that outlives the returned reference.
+    #[inline]
+    pub const unsafe fn from_base(base: usize) -> &'static Self {
+        const { assert!(HARTS <= MAX_HARTS) };
+        unsafe { &*(base as *const Self) }
+    }
+
+    /// Read `MTIME` register.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn read_mtime(&self) -> u64 {
+        unsafe { load_u64(&self.mtime.0) }
+    }
+
+    /// Write `MTIME` register.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn write_mtime(&self, val: u64) {
+        unsafe { store(&self.mtime.0, val) }
+    }
+
+    /// Read `MTIMECMP` register for the given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn read_mtimecmp(&self, hart_idx: usize) -> u64 {
+        unsafe { load_u64(&self.mtimecmp[hart_idx].0) }
+    }
+
+    /// Write `MTIMECMP` register for the given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn write_mtimecmp(&self, hart_idx: usize, val: u64) {
+        unsafe { store_mtimecmp(&self.mtimecmp[hart_idx].0, val) }
+    }
+
+    /// Read `MTIMECMP` register for the given hart, checking the index.
+    #[inline]
+    pub fn try_read_mtimecmp(&self, hart_idx: usize) -> Result<u64, InvalidHartId> {
+        Ok(unsafe { load_u64(&self.checked_mtimecmp(hart_idx)?.0) })
+    }
+
+    /// Write `MTIMECMP` register for the given hart, checking the index.
+    #[inline]
+    pub fn try_write_mtimecmp(&self, hart_idx: usize, val: u64) -> Result<(), InvalidHartId> {
+        unsafe { store_mtimecmp(&self.checked_mtimecmp(hart_idx)?.0, val) };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn checked_mtimecmp(&self, hart_idx: usize) -> Result<&MTIMECMP, InvalidHartId> {
+        self.mtimecmp.get(hart_idx).ok_or(InvalidHartId(hart_idx))
+    }
+}
+
+/// Supervisor-level Software Interrupt Device (SSWI) covering `HARTS` harts,
+/// at most [`MAX_HARTS`].
+///
+/// Like [`Mswi`], only the `SETSSIP` registers of the [`SSWI_SIZE`] window and the reserved
+/// word after them are included.
 #[repr(C)]
-pub struct SSWI {
-    pub setssip: [SETSSIP; 4095],
+pub struct Sswi<const HARTS: usize> {
+    pub setssip: [SETSSIP; HARTS],
     _reserved: u32,
 }
 
-/// SiFive Core-Local Interruptor (CLINT) device.
+/// SSWI device covering all [`MAX_HARTS`] harts.
+pub type SSWI = Sswi<MAX_HARTS>;
+
+impl<const HARTS: usize> Sswi<HARTS> {
+    /// Refer to the SSWI device at `base`, as found in its own device tree node.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the address of a mapped SSWI device that outlives the returned reference.
+    #[inline]
+    pub const unsafe fn from_base(base: usize) -> &'static Self {
+        const { assert!(HARTS <= MAX_HARTS) };
+        unsafe { &*(base as *const Self) }
+    }
+
+    /// Read supervisor-level software interrupt state for given hart.
+    ///
+    /// The ACLINT spec has `SETSSIP` read as zero on real devices;
+    /// the pending state lives in `mip.SSIP` of the target hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn read_ssip(&self, hart_idx: usize) -> bool {
+        unsafe { load(&self.setssip[hart_idx].0) != 0 }
+    }
+
+    /// Set supervisor-level software interrupt for given hart.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn set_ssip(&self, hart_idx: usize) {
+        unsafe { store(&self.setssip[hart_idx].0, 1) }
+    }
+
+    /// Write 0 to `SETSSIP` of given hart.
+    ///
+    /// The ACLINT spec makes this write a no-op on real devices; the target hart clears
+    /// `sip.SSIP` itself.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    pub fn clear_ssip(&self, hart_idx: usize) {
+        unsafe { store(&self.setssip[hart_idx].0, 0) }
+    }
+}
+
+/// Errors reported by this crate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AclintError {
+    /// A device region is shorter than the device.
+    RegionTooSmall {
+        /// Name of the device, such as `"MTIMER"`.
+        device: &'static str,
+        /// Length of the region given.
+        len: usize,
+        /// Size the device requires.
+        required: usize,
+    },
+    /// A hart mask selects a hart the device does not cover.
+    HartOutOfRange {
+        /// The first such hart id.
+        hart_id: usize,
+        /// Number of harts the device covers.
+        capacity: usize,
+    },
+}
+
+impl core::fmt::Display for AclintError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::RegionTooSmall {
+                device,
+                len,
+                required,
+            } => write!(
+                f,
+                "{device} region of {len:#x} bytes is smaller than {required:#x}"
+            ),
+            Self::HartOutOfRange { hart_id, capacity } => {
+                write!(f, "hart {hart_id} is beyond the {capacity} harts covered")
+            }
+        }
+    }
+}
+
+/// A hart index beyond the harts a device covers, as returned by the `try_*` accessors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidHartId(pub usize);
+
+impl core::fmt::Display for InvalidHartId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "hart index {} is out of range", self.0)
+    }
+}
+
+/// Why [`SifiveClint::arm_verified`] could not arm a timer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArmError {
+    /// The hart index is beyond the harts the device covers; nothing was written.
+    InvalidHart(InvalidHartId),
+    /// The write did not stick; holds the value read back.
+    Mismatch(u64),
+}
+
+impl core::fmt::Display for ArmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidHart(e) => write!(f, "{e}"),
+            Self::Mismatch(actual) => write!(f, "mtimecmp read back as {actual:#x}"),
+        }
+    }
+}
+
+/// Harts selected by an SBI `(hart_mask, hart_mask_base)` argument pair.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HartMask {
+    /// Bit `i` selects hart `base + i`.
+    pub mask: usize,
+    /// Hart id of bit 0, or `usize::MAX` to select all harts.
+    pub base: usize,
+}
+
+impl HartMask {
+    /// Mask from the two arguments of an SBI call.
+    #[inline]
+    pub const fn from_mask_base(mask: usize, base: usize) -> Self {
+        Self { mask, base }
+    }
+}
+
+/// Check that every hart selected by `mask` is below `capacity`, so that IPI handlers can
+/// reject a malformed mask before touching any register.
+///
+/// A mask selecting all harts always passes, since it means all harts that exist.
+/// Returns [`AclintError::HartOutOfRange`] for the lowest offending hart.
+#[inline]
+pub fn validate_mask(mask: &HartMask, capacity: usize) -> Result<(), AclintError> {
+    if mask.base == usize::MAX {
+        return Ok(());
+    }
+    match (0..usize::BITS as usize)
+        .filter(|i| mask.mask & (1 << i) != 0)
+        .map(|i| mask.base.saturating_add(i))
+        .find(|&hart_id| hart_id >= capacity)
+    {
+        Some(hart_id) => Err(AclintError::HartOutOfRange { hart_id, capacity }),
+        None => Ok(()),
+    }
+}
+
+/// ACLINT devices found on a platform; each of them is optional.
+pub struct Aclint {
+    /// Machine-level software interrupt device.
+    pub mswi: Option<&'static MSWI>,
+    /// Machine-level timer device.
+    pub mtimer: Option<&'static MTIMER>,
+    /// Supervisor-level software interrupt device.
+    pub sswi: Option<&'static SSWI>,
+}
+
+impl Aclint {
+    /// Build from `(base, len)` region descriptors, usually taken from device tree `reg`
+    /// properties, checking each given length against [`MSWI_SIZE`], [`MTIMER_SIZE`]
+    /// and [`SSWI_SIZE`].
+    ///
+    /// # Safety
+    ///
+    /// Each given base must be the address of the corresponding device, mapped for `'static`.
+    pub unsafe fn from_regions(
+        mswi: Option<(usize, usize)>,
+        mtimer: Option<(usize, usize)>,
+        sswi: Option<(usize, usize)>,
+    ) -> Result<Self, AclintError> {
+        fn check(
+            region: Option<(usize, usize)>,
+            device: &'static str,
+            required: usize,
+        ) -> Result<Option<usize>, AclintError> {
+            match region {
+                Some((_, len)) if len < required => Err(AclintError::RegionTooSmall {
+                    device,
+                    len,
+                    required,
+                }),
+                region => Ok(region.map(|(base, _)| base)),
+            }
+        }
+        let mswi = check(mswi, "MSWI", MSWI_SIZE)?;
+        let mtimer = check(mtimer, "MTIMER", MTIMER_SIZE)?;
+        let sswi = check(sswi, "SSWI", SSWI_SIZE)?;
+        Ok(Self {
+            mswi: mswi.map(|base| unsafe { MSWI::from_base(base) }),
+            mtimer: mtimer.map(|base| unsafe { MTIMER::from_base(base) }),
+            sswi: sswi.map(|base| unsafe { SSWI::from_base(base) }),
+        })
+    }
+}
+
+/// SiFive Core-Local Interruptor (CLINT) device covering `HARTS` harts, at most [`MAX_HARTS`].
+///
+/// The SiFive layout puts each block at a fixed offset, so the device always spans
+/// [`SIFIVE_CLINT_SIZE`] bytes; `HARTS` only bounds the per-hart registers.
 #[repr(C)]
-pub struct SifiveClint {
+pub struct Clint<const HARTS: usize> {
     /// Machine-level inter-processor (or software) interrupts.
-    pub mswi: MSWI,
+    pub mswi: Padded<Mswi<HARTS>, MSWI_SIZE>,
     /// Machine-level fixed-frequency counter and timer events; compartion part.
-    pub mtimecmp: [MTIMECMP; 4095],
+    pub mtimecmp: Padded<[MTIMECMP; HARTS], 0x7ff8>,
     /// Machine-level fixed-frequency counter and timer events; number of cycles part.
     pub mtime: MTIME,
 }
 
-impl SifiveClint {
-    /// Read `MTIME` register.
+/// SiFive CLINT covering all [`MAX_HARTS`] harts.
+pub type SifiveClint = Clint<MAX_HARTS>;
+
+// `SifiveClint::split` relies on the timer part having exactly the layout of `MTIMER`.
+const _: () = assert!(
+    core::mem::offset_of!(SifiveClint, mtime) - core::mem::offset_of!(SifiveClint, mtimecmp)
+        == core::mem::offset_of!(MTIMER, mtime)
+);
+
+impl<const HARTS: usize> Clint<HARTS> {
+    /// Refer to the CLINT at `base`, such as the start of the device tree `reg` of a
+    /// `riscv,clint0` node.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the address, in the current address space, of a CLINT with the SiFive
+    /// layout, mapped for all [`SIFIVE_CLINT_SIZE`] bytes for as long as the program runs.
+    /// It must be 8-byte aligned, which every real CLINT is, and the region must be used
+    /// only as this device, never as ordinary memory.
+    #[inline]
+    pub const unsafe fn from_base(base: usize) -> &'static Self {
+        const { assert!(HARTS <= MAX_HARTS) };
+        unsafe { &*(base as *const Self) }
+    }
+
+    /// Address of the device, the inverse of [`Clint::from_base`].
+    #[inline]
+    pub fn base_address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Sub-regions of the device as `(offset, len)` pairs: the `MSIP` registers,
+    /// the timer block (`MTIMECMP` and `MTIME`), and the reserved space after the last `MSIP`.
+    ///
+    /// Together they cover all [`SIFIVE_CLINT_SIZE`] bytes, so the software interrupt and
+    /// timer parts can be mapped with different permissions.
     #[inline]
+    pub const fn regions() -> [(usize, usize); 3] {
+        let msip_len = HARTS * 4;
+        [
+            (MSIP_OFFSET, msip_len),
+            (MTIMECMP_OFFSET, MTIMER_SIZE),
+            (MSIP_OFFSET + msip_len, MSWI_SIZE - msip_len),
+        ]
+    }
+
+    /// Split into the software interrupt device and the timer device,
+    /// so that IPI and timer can be handed to different subsystems.
+    ///
+    /// Both halves still refer to the same MMIO region as `self`.
+    #[inline]
+    pub fn split(&self) -> (&Mswi<HARTS>, &Mtimer<HARTS>) {
+        (&self.mswi, self.mtimer())
+    }
+
+    /// The timer block viewed as an [`Mtimer`] device.
+    #[inline(always)]
+    fn mtimer(&self) -> &Mtimer<HARTS> {
+        const { assert!(HARTS <= MAX_HARTS) };
+        let base = (self as *const Self).cast::<u8>();
+        unsafe {
+            &*base
+                .add(core::mem::offset_of!(Self, mtimecmp))
+                .cast::<Mtimer<HARTS>>()
+        }
+    }
+
+    /// Read `MTIME` register.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn read_mtime(&self) -> u64 {
-        unsafe { self.mtime.0.get().read_volatile() }
+        self.mtimer().read_mtime()
     }
 
-    /// Write `MTIME` register.
+    /// Read `MTIME` register with the memory ordering of an atomic load.
+    ///
+    /// A volatile MMIO read followed or surrounded by fences approximates an atomic load of
+    /// this shared counter: `Relaxed` is the plain read, `Acquire` fences after the read so
+    /// later accesses are not reordered before it, and `SeqCst` fences on both sides.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is `Release` or `AcqRel`, like `AtomicU64::load`.
     #[inline]
+    pub fn load_mtime(&self, order: Ordering) -> u64 {
+        match order {
+            Ordering::Relaxed => self.read_mtime(),
+            Ordering::Acquire => {
+                let ans = self.read_mtime();
+                fence(Ordering::Acquire);
+                ans
+            }
+            Ordering::SeqCst => {
+                fence(Ordering::SeqCst);
+                let ans = self.read_mtime();
+                fence(Ordering::SeqCst);
+                ans
+            }
+            Ordering::Release | Ordering::AcqRel => {
+                panic!("there is no such thing as a release load")
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Write `MTIME` register.
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn write_mtime(&self, val: u64) {
-        unsafe { self.mtime.0.get().write_volatile(val) }
+        self.mtimer().write_mtime(val)
     }
 
     /// Read `MTIMECMP` register for the given hart.
-    #[inline]
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn read_mtimecmp(&self, hart_idx: usize) -> u64 {
-        unsafe { self.mtimecmp[hart_idx].0.get().read_volatile() }
+        self.mtimer().read_mtimecmp(hart_idx)
     }
 
     /// Write `MTIMECMP` register for the given hart.
-    #[inline]
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn write_mtimecmp(&self, hart_idx: usize, val: u64) {
-        unsafe { self.mtimecmp[hart_idx].0.get().write_volatile(val) }
+        self.mtimer().write_mtimecmp(hart_idx, val)
     }
 
     /// Read machine-level software interrupt state for given hart.
-    #[inline]
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn read_msip(&self, hart_idx: usize) -> bool {
-        unsafe { self.mswi.msip[hart_idx].0.get().read_volatile() != 0 }
+        self.mswi.read_msip(hart_idx)
     }
 
     /// Set machine-level software interrupt for given hart.
-    #[inline]
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn set_msip(&self, hart_idx: usize) {
-        unsafe { self.mswi.msip[hart_idx].0.get().write_volatile(1) }
+        self.mswi.set_msip(hart_idx)
     }
 
     /// Clear machine-level software interrupt for given hart.
-    #[inline]
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
     pub fn clear_msip(&self, hart_idx: usize) {
-        unsafe { self.mswi.msip[hart_idx].0.get().write_volatile(0) }
+        self.mswi.clear_msip(hart_idx)
     }
-}
 
-#[test]
-fn test() {
-    assert_eq!(core::mem::size_of::<MSWI>(), 0x4000);
-    assert_eq!(core::mem::size_of::<SSWI>(), 0x4000);
-    assert_eq!(core::mem::size_of::<[MTIMECMP; 4095]>(), 0x7ff8);
-    assert_eq!(core::mem::size_of::<SifiveClint>(), 0xc000);
+    /// Read `MTIMECMP` register for the given hart, checking the index.
+    #[inline]
+    pub fn try_read_mtimecmp(&self, hart_idx: usize) -> Result<u64, InvalidHartId> {
+        self.mtimer().try_read_mtimecmp(hart_idx)
+    }
+
+    /// Read `MTIMECMP` register for the given hart, or `None` if `hart_idx` is not below 4095.
+    #[inline]
+    pub fn read_mtimecmp_checked(&self, hart_idx: usize) -> Option<u64> {
+        self.try_read_mtimecmp(hart_idx).ok()
+    }
+
+    /// Write `MTIMECMP` register for the given hart, checking the index.
+    #[inline]
+    pub fn try_write_mtimecmp(&self, hart_idx: usize, val: u64) -> Result<(), InvalidHartId> {
+        self.mtimer().try_write_mtimecmp(hart_idx, val)
+    }
+
+    /// Read machine-level software interrupt state for given hart, checking the index.
+    #[inline]
+    pub fn try_read_msip(&self, hart_idx: usize) -> Result<bool, InvalidHartId> {
+        self.mswi.try_read_msip(hart_idx)
+    }
+
+    /// Set machine-level software interrupt for given hart, checking the index.
+    #[inline]
+    pub fn try_set_msip(&self, hart_idx: usize) -> Result<(), InvalidHartId> {
+        self.mswi.try_set_msip(hart_idx)
+    }
+
+    /// Clear machine-level software interrupt for given hart, checking the index.
+    #[inline]
+    pub fn try_clear_msip(&self, hart_idx: usize) -> Result<(), InvalidHartId> {
+        self.mswi.try_clear_msip(hart_idx)
+    }
+
+    /// Reset given hart to its power-on state: clear `MSIP` and write 0 to `MTIMECMP`.
+    ///
+    /// This mimics a hardware reset, where `MTIMECMP` comes up as 0 and the timer fires as
+    /// soon as `MTIME` is above 0. To park a hart so that its timer never fires, write
+    /// `u64::MAX` to `MTIMECMP` instead.
+    #[inline]
+    pub fn reset_hart_state(&self, hart_idx: usize) {
+        self.clear_msip(hart_idx);
+        self.write_mtimecmp(hart_idx, 0);
+    }
+
+    /// Write `deadline` to `MTIMECMP` of given hart and read it back.
+    ///
+    /// Returns [`ArmError::Mismatch`] with the value read back if the write did not stick,
+    /// for example because the region is not really a CLINT or is mapped read-only.
+    /// A `hart_idx` not below `HARTS` is not written and gives [`ArmError::InvalidHart`].
+    #[inline]
+    pub fn arm_verified(&self, hart_idx: usize, deadline: u64) -> Result<(), ArmError> {
+        let Some(mtimecmp) = self.mtimecmp.get(hart_idx) else {
+            return Err(ArmError::InvalidHart(InvalidHartId(hart_idx)));
+        };
+        verify_write(
+            || unsafe { store_mtimecmp(&mtimecmp.0, deadline) },
+            || unsafe { load_u64(&mtimecmp.0) },
+            deadline,
+        )
+        .map_err(ArmError::Mismatch)
+    }
+
+    /// Arm the first `hart_count` harts (at most `HARTS`) to the same `deadline`, reading each
+    /// `MTIMECMP` back as [`arm_verified`](Self::arm_verified) does.
+    ///
+    /// Returns how many of the writes stuck, so a partially mapped or read-only region
+    /// shows up as a count below `hart_count`.
+    #[inline]
+    pub fn arm_all_checked(&self, hart_count: usize, deadline: u64) -> usize {
+        self.arm_all_with(hart_count, |hart_idx| self.arm_verified(hart_idx, deadline))
+    }
+
+    /// Count the first `hart_count` harts (at most `HARTS`) for which `arm` succeeds.
+    #[inline]
+    fn arm_all_with(
+        &self,
+        hart_count: usize,
+        arm: impl FnMut(usize) -> Result<(), ArmError>,
+    ) -> usize {
+        count_verified(hart_count.min(HARTS), arm)
+    }
+
+    /// Move the deadline of given hart earlier to `candidate`, never later,
+    /// and return the resulting deadline.
+    ///
+    /// Deadlines are compared with wrapping arithmetic, so both must lie within half of the
+    /// `MTIME` range (2^63 ticks) of each other. A disarmed timer (`u64::MAX`) always takes
+    /// `candidate`. This is a read-modify-write; only one agent should program a hart's timer.
+    #[inline]
+    pub fn pull_in_mtimecmp(&self, hart_idx: usize, candidate: u64) -> u64 {
+        let current = self.read_mtimecmp(hart_idx);
+        if current == u64::MAX || (candidate.wrapping_sub(current) as i64) < 0 {
+            self.write_mtimecmp(hart_idx, candidate);
+            candidate
+        } else {
+            current
+        }
+    }
+
+    /// Time left until `MTIME` reaches `deadline` at frequency `freq`.
+    ///
+    /// Returns `None` if the deadline already passed, compared with wrapping arithmetic
+    /// like [`pull_in_mtimecmp`](Self::pull_in_mtimecmp), or if `freq` is zero.
+    /// A deadline equal to the current `MTIME` gives [`Duration::ZERO`].
+    #[cfg(feature = "duration")]
+    #[inline]
+    pub fn time_until(&self, deadline: u64, freq: TimebaseFrequency) -> Option<Duration> {
+        let ticks = deadline.wrapping_sub(self.read_mtime()) as i64;
+        if ticks < 0 || freq.0 == 0 {
+            return None;
+        }
+        let (ticks, freq) = (ticks as u64, freq.0);
+        let nanos = (ticks % freq) as u128 * 1_000_000_000 / freq as u128;
+        Some(Duration::new(ticks / freq, nanos as u32))
+    }
+
+    /// Program `MTIMECMP` of `hart_idx` to `base_deadline` plus an offset in `0..jitter_ticks`
+    /// derived from `hart_idx` and `seed`, and return the programmed deadline.
+    ///
+    /// The offset comes from a fixed hash, so the same hart and seed always get the same
+    /// deadline. It only spreads periodic timers of different harts apart to avoid contention;
+    /// it is predictable and must not be used where randomness matters.
+    #[inline]
+    pub fn arm_jittered(
+        &self,
+        hart_idx: usize,
+        base_deadline: u64,
+        jitter_ticks: u64,
+        seed: u64,
+    ) -> u64 {
+        let offset = match jitter_ticks {
+            0 => 0,
+            n => splitmix64(seed ^ hart_idx as u64) % n,
+        };
+        let deadline = base_deadline.wrapping_add(offset);
+        self.write_mtimecmp(hart_idx, deadline);
+        deadline
+    }
+
+    /// Program `MTIMECMP` of `hart_idx` to the deadline currently set for `reference_hart`,
+    /// so that both harts wake up together, and return that deadline.
+    ///
+    /// Nothing is written if either index is not below `HARTS`; the first bad one is returned.
+    #[inline]
+    pub fn align_timer_to(
+        &self,
+        hart_idx: usize,
+        reference_hart: usize,
+    ) -> Result<u64, InvalidHartId> {
+        let target = self.mtimer().checked_mtimecmp(hart_idx)?;
+        let deadline = self.try_read_mtimecmp(reference_hart)?;
+        unsafe { store_mtimecmp(&target.0, deadline) };
+        Ok(deadline)
+    }
+
+    /// Iterate `(hart, deadline)` of harts among the first `hart_count` (at most `HARTS`)
+    /// whose `MTIMECMP` is armed, i.e. not `u64::MAX`.
+    ///
+    /// Each `MTIMECMP` is read once, when the iterator reaches it.
+    #[inline]
+    pub fn armed_timers(&self, hart_count: usize) -> impl Iterator<Item = (usize, u64)> + '_ {
+        (0..hart_count.min(self.mtimecmp.len()))
+            .map(|i| (i, self.read_mtimecmp(i)))
+            .filter(|&(_, deadline)| deadline != u64::MAX)
+    }
+
+    /// Earliest deadline among the armed timers of the first `hart_count` harts
+    /// (at most `HARTS`), or `None` if none is armed.
+    ///
+    /// `MTIME` is read once as the baseline for [`compare_deadlines`], so a deadline that
+    /// already passed counts as earliest.
+    #[inline]
+    pub fn soonest_deadline(&self, hart_count: usize) -> Option<u64> {
+        let mtime = self.read_mtime();
+        self.armed_timers(hart_count)
+            .map(|(_, deadline)| deadline)
+            .min_by(|&a, &b| compare_deadlines(a, b, mtime))
+    }
+
+    /// Copy `MTIMECMP` of harts `0..out.len()` into `out`, stopping at the last hart.
+    #[inline]
+    pub fn save_timers(&self, out: &mut [u64]) {
+        for (hart_idx, deadline) in out.iter_mut().take(self.mtimecmp.len()).enumerate() {
+            *deadline = self.read_mtimecmp(hart_idx);
+        }
+    }
+
+    /// Write `saved[hart_idx]` back to `MTIMECMP` of each hart, stopping at the last hart.
+    ///
+    /// Restoring a deadline that already passed makes the timer interrupt pending at once.
+    #[inline]
+    pub fn restore_timers(&self, saved: &[u64]) {
+        for (hart_idx, &deadline) in saved.iter().take(self.mtimecmp.len()).enumerate() {
+            self.write_mtimecmp(hart_idx, deadline);
+        }
+    }
+
+    /// Check that `MTIME` is counting: read it, spin `spin` iterations, and read it again.
+    ///
+    /// This is a heuristic. A frozen counter, or an address that does not decode to a
+    /// CLINT, keeps reading the same value; but a slow timebase may also not tick within a
+    /// short spin, so pick `spin` long enough to cover at least one tick.
+    #[inline]
+    pub fn mtime_is_running(&self, spin: usize) -> bool {
+        is_running(self, spin)
+    }
+
+    /// Interrupts pending on the first `hart_count` harts (at most `HARTS`), in hart order,
+    /// with the software interrupt of a hart before its timer interrupt.
+    ///
+    /// `MTIME` is read once up front, and each `MSIP` and `MTIMECMP` once as the iterator
+    /// advances; a timer is pending when its `MTIMECMP` is not after that `MTIME`.
+    #[inline]
+    pub fn pending_interrupts(
+        &self,
+        hart_count: usize,
+    ) -> impl Iterator<Item = (usize, IntKind)> + '_ {
+        let mtime = self.read_mtime();
+        (0..hart_count.min(self.mtimecmp.len())).flat_map(move |i| {
+            let software = self.read_msip(i).then_some((i, IntKind::Software));
+            let timer = (self.read_mtimecmp(i) <= mtime).then_some((i, IntKind::Timer));
+            software.into_iter().chain(timer)
+        })
+    }
+
+    /// Whether the software and the timer interrupt of the current hart are pending,
+    /// so that a handler can check without being told its hart id.
+    ///
+    /// The hart is found by reading `mhartid`, so this must run in M-mode, and hart ids
+    /// must be the same as the hart indexes of this device.
+    #[cfg(feature = "riscv")]
+    #[inline]
+    pub fn my_pending(&self) -> (bool, bool) {
+        let hart_idx = riscv::register::mhartid::read();
+        let software = self.read_msip(hart_idx);
+        let timer = self.read_mtimecmp(hart_idx) <= self.read_mtime();
+        (software, timer)
+    }
+
+    /// Write `deadline` to `MTIMECMP` of given hart and set `mie.MTIE`, returning a guard
+    /// that clears `mie.MTIE` and disarms the timer with `u64::MAX` when dropped.
+    ///
+    /// This scopes a timer to a block, such as a bounded wait, so that no early return
+    /// leaves it armed. Must run in M-mode on the hart `hart_idx`. Passing the guard to
+    /// [`core::mem::forget`] skips the drop and leaves the timer armed and enabled.
+    #[cfg(feature = "riscv")]
+    #[inline]
+    pub fn arm_timer_guarded(&self, hart_idx: usize, deadline: u64) -> TimerGuard<'_, HARTS> {
+        self.write_mtimecmp(hart_idx, deadline);
+        set_mtie(true);
+        TimerGuard {
+            clint: self,
+            hart_idx,
+        }
+    }
+
+    /// Write `MTIME` and a table of `MTIMECMP` and `MSIP` of the first `harts` harts
+    /// (at most `HARTS`) to `f`, all in hex.
+    ///
+    /// Every register is read once with a volatile read, so the dump is a live snapshot.
+    pub fn hexdump(&self, f: &mut impl core::fmt::Write, harts: usize) -> core::fmt::Result {
+        writeln!(f, "mtime {:#018x}", self.read_mtime())?;
+        writeln!(f, "hart mtimecmp           msip")?;
+        for hart_idx in 0..harts.min(HARTS) {
+            writeln!(
+                f,
+                "{hart_idx:4x} {:#018x} {:x}",
+                self.read_mtimecmp(hart_idx),
+                self.read_msip(hart_idx) as u8
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Spin until machine-level software interrupt for given hart is set,
+    /// polling at most `max_spins` times (usually [`DEFAULT_MAX_SPINS`]).
+    ///
+    /// Returns `false` if it was not observed before the bound ran out.
+    #[inline]
+    pub fn wait_msip(&self, hart_idx: usize, max_spins: usize) -> bool {
+        for _ in 0..max_spins {
+            if self.read_msip(hart_idx) {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+
+    /// Spin until `MTIME` reaches `MTIMECMP` of given hart,
+    /// polling at most `max_spins` times (usually [`DEFAULT_MAX_SPINS`]).
+    ///
+    /// Returns `false` if the timer was not due before the bound ran out.
+    #[inline]
+    pub fn wait_timer(&self, hart_idx: usize, max_spins: usize) -> bool {
+        for _ in 0..max_spins {
+            if self.read_mtime() >= self.read_mtimecmp(hart_idx) {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+}
+
+// SAFETY: the registers are only accessed through volatile reads and writes of a shared
+// MMIO device, where the hardware arbitrates concurrent accesses from different harts.
+// This is what allows firmware to share `&'static SifiveClint` across harts.
+unsafe impl<const HARTS: usize> Sync for Mswi<HARTS> {}
+unsafe impl<const HARTS: usize> Sync for Sswi<HARTS> {}
+unsafe impl<const HARTS: usize> Sync for Mtimer<HARTS> {}
+unsafe impl<const HARTS: usize> Sync for Clint<HARTS> {}
+
+/// Timer armed by [`SifiveClint::arm_timer_guarded`], disarmed when dropped.
+#[cfg(feature = "riscv")]
+#[must_use = "dropping the guard disarms the timer at once"]
+pub struct TimerGuard<'a, const HARTS: usize> {
+    clint: &'a Clint<HARTS>,
+    hart_idx: usize,
+}
+
+#[cfg(feature = "riscv")]
+impl<const HARTS: usize> Drop for TimerGuard<'_, HARTS> {
+    #[inline]
+    fn drop(&mut self) {
+        set_mtie(false);
+        self.clint.write_mtimecmp(self.hart_idx, u64::MAX);
+    }
+}
+
+/// Set or clear `mie.MTIE`. There is no `mie` on other architectures, where this does
+/// nothing so that host tests can run.
+#[cfg(feature = "riscv")]
+#[inline]
+fn set_mtie(enable: bool) {
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        if enable {
+            riscv::register::mie::set_mtimer()
+        } else {
+            riscv::register::mie::clear_mtimer()
+        }
+    }
+    #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+    let _ = enable;
+}
+
+/// Kind of a machine-level interrupt raised by a CLINT.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IntKind {
+    /// `MSIP` is set.
+    Software,
+    /// `MTIMECMP` has been reached.
+    Timer,
+}
+
+/// Frequency of `MTIME` in Hz, as given by the device tree `timebase-frequency`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimebaseFrequency(pub u64);
+
+impl TimebaseFrequency {
+    /// Seconds until a counter `bits` wide wraps around at this frequency.
+    ///
+    /// The full 64-bit `MTIME` practically never wraps, but a 32-bit read of it (the low
+    /// half on RV32) may wrap within minutes. Returns `u64::MAX` for a zero frequency.
+    /// A `bits` above 64 is taken as 64, the width of `MTIME`.
+    #[inline]
+    pub const fn rollover_seconds_for(self, bits: u32) -> u64 {
+        if self.0 == 0 {
+            return u64::MAX;
+        }
+        let bits = if bits > 64 { 64 } else { bits };
+        let secs = (1u128 << bits) / self.0 as u128;
+        if secs > u64::MAX as u128 {
+            u64::MAX
+        } else {
+            secs as u64
+        }
+    }
+
+    /// Nanoseconds of one tick, rounded up; no timer request finer than this can be honored.
+    ///
+    /// Returns `u64::MAX` for a zero frequency.
+    #[inline]
+    pub const fn min_representable_nanos(&self) -> u64 {
+        if self.0 == 0 {
+            return u64::MAX;
+        }
+        1_000_000_000u64.div_ceil(self.0)
+    }
+}
+
+/// Seconds until the 64-bit `MTIME` wraps around at `freq`.
+#[inline]
+pub const fn rollover_seconds(freq: TimebaseFrequency) -> u64 {
+    freq.rollover_seconds_for(64)
+}
+
+/// Deadline of the `n`-th period of length `period` after `epoch`.
+///
+/// Computing every tick from a fixed epoch, instead of adding `period` to the previous
+/// deadline, keeps a periodic timer from accumulating error. The arithmetic wraps like
+/// `MTIME` does; a huge `n` whose `period * n` exceeds 64 bits silently wraps too, so keep
+/// `period * n` below 2^63 for the result to be ordered correctly by [`compare_deadlines`].
+#[inline]
+pub const fn nth_period_deadline(epoch: u64, period: u64, n: u64) -> u64 {
+    epoch.wrapping_add(period.wrapping_mul(n))
+}
+
+/// Order deadlines `a` and `b` by how far each lies after `now`, with wrapping arithmetic,
+/// so that a queue of deadlines stays sorted across the wraparound of `MTIME`.
+///
+/// Both deadlines must lie within half of the `MTIME` range (2^63 ticks) of `now`;
+/// a deadline that already passed sorts before any that has not.
+#[inline]
+pub const fn compare_deadlines(a: u64, b: u64, now: u64) -> core::cmp::Ordering {
+    let a = a.wrapping_sub(now) as i64;
+    let b = b.wrapping_sub(now) as i64;
+    if a < b {
+        core::cmp::Ordering::Less
+    } else if a > b {
+        core::cmp::Ordering::Greater
+    } else {
+        core::cmp::Ordering::Equal
+    }
+}
+
+/// Source of the current `MTIME` value.
+///
+/// Implemented by the MMIO devices; timer utilities built on top of it can also be driven
+/// by a scripted clock in host-side tests.
+pub trait TimeRead {
+    /// Current `MTIME` value.
+    fn now(&self) -> u64;
+}
+
+/// Perform `write`, then compare what `read` returns with `expected`.
+#[inline]
+fn verify_write(
+    write: impl FnOnce(),
+    read: impl FnOnce() -> u64,
+    expected: u64,
+) -> Result<(), u64> {
+    write();
+    match read() {
+        actual if actual == expected => Ok(()),
+        actual => Err(actual),
+    }
+}
+
+/// Count harts `0..hart_count` for which `arm` succeeds.
+#[inline]
+fn count_verified<E>(hart_count: usize, mut arm: impl FnMut(usize) -> Result<(), E>) -> usize {
+    (0..hart_count)
+        .filter(|&hart_idx| arm(hart_idx).is_ok())
+        .count()
+}
+
+/// SplitMix64 finalizer, a cheap well-mixed hash of `x`.
+#[inline]
+const fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn is_running(clock: &impl TimeRead, spin: usize) -> bool {
+    let t0 = clock.now();
+    for _ in 0..spin {
+        core::hint::spin_loop();
+    }
+    clock.now() != t0
+}
+
+impl<const HARTS: usize> TimeRead for Mtimer<HARTS> {
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    fn now(&self) -> u64 {
+        self.read_mtime()
+    }
+}
+
+impl<const HARTS: usize> TimeRead for Clint<HARTS> {
+    #[cfg_attr(feature = "inline-always", inline(always))]
+    #[cfg_attr(not(feature = "inline-always"), inline)]
+    fn now(&self) -> u64 {
+        self.read_mtime()
+    }
+}
+
+/// Non-wrapping 128-bit tick count accumulated from successive `MTIME` samples.
+///
+/// A sample smaller than the previous one is taken as a 64-bit wraparound and carried
+/// into the upper half, so the total keeps increasing. [`update`](Self::update) must be
+/// called at least once per `MTIME` period for this to hold.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MonotonicTicks {
+    last: u64,
+    wraps: u64,
+}
+
+impl MonotonicTicks {
+    /// Create a tick counter starting from `MTIME` value 0.
+    #[inline]
+    pub const fn new() -> Self {
+        Self { last: 0, wraps: 0 }
+    }
+
+    /// Sample `MTIME` and return the total ticks counted so far.
+    #[inline]
+    pub fn update(&mut self, clock: &impl TimeRead) -> u128 {
+        let now = clock.now();
+        if now < self.last {
+            self.wraps += 1;
+        }
+        self.last = now;
+        ((self.wraps as u128) << 64) | now as u128
+    }
+}
+
+/// Fixed-capacity queue of up to `N` deadlines, each with a caller-chosen token,
+/// kept in deadline order.
+///
+/// Deadlines are compared with wrapping arithmetic like
+/// [`SifiveClint::pull_in_mtimecmp`], so all queued deadlines must lie within half of the
+/// `MTIME` range (2^63 ticks) of each other. Entries with equal deadlines leave the queue in
+/// the order they were pushed. Program the timer with [`peek`](Self::peek) after each change.
+#[derive(Clone, Copy, Debug)]
+pub struct TimerQueue<const N: usize> {
+    len: usize,
+    entries: [(u64, usize); N],
+}
+
+impl<const N: usize> TimerQueue<N> {
+    /// Create an empty queue.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            len: 0,
+            entries: [(0, 0); N],
+        }
+    }
+
+    /// Number of queued entries.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the queue has no entries.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Earliest queued deadline.
+    #[inline]
+    pub fn peek(&self) -> Option<u64> {
+        self.entries[..self.len]
+            .first()
+            .map(|&(deadline, _)| deadline)
+    }
+
+    /// Queue `token` to expire at `deadline`.
+    ///
+    /// Returns the entry back if the queue already holds `N` entries.
+    #[inline]
+    pub fn push(&mut self, deadline: u64, token: usize) -> Result<(), (u64, usize)> {
+        if self.len == N {
+            return Err((deadline, token));
+        }
+        let at = self.entries[..self.len]
+            .iter()
+            .position(|&(queued, _)| (deadline.wrapping_sub(queued) as i64) < 0)
+            .unwrap_or(self.len);
+        self.entries.copy_within(at..self.len, at + 1);
+        self.entries[at] = (deadline, token);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Sample `clock` and remove the earliest entry if `MTIME` has reached its deadline,
+    /// returning `(deadline, token)`.
+    ///
+    /// Call it in a loop to drain every expired entry. An empty queue does not sample `clock`.
+    #[inline]
+    pub fn pop_expired(&mut self, clock: &impl TimeRead) -> Option<(u64, usize)> {
+        let &(deadline, token) = self.entries[..self.len].first()?;
+        if (clock.now().wrapping_sub(deadline) as i64) < 0 {
+            return None;
+        }
+        self.entries.copy_within(1..self.len, 0);
+        self.len -= 1;
+        Some((deadline, token))
+    }
+}
+
+impl<const N: usize> Default for TimerQueue<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+fn assert_layout<const HARTS: usize>() {
+    use core::mem::{offset_of, size_of};
+    assert_eq!(size_of::<Mswi<HARTS>>(), (HARTS + 1) * 4);
+    assert_eq!(size_of::<Sswi<HARTS>>(), (HARTS + 1) * 4);
+    assert_eq!(offset_of!(Mswi<HARTS>, _reserved), HARTS * 4);
+    assert_eq!(size_of::<Mtimer<HARTS>>(), MTIMER_SIZE);
+    assert_eq!(size_of::<Clint<HARTS>>(), SIFIVE_CLINT_SIZE);
+    assert_eq!(offset_of!(Clint<HARTS>, mtimecmp), MTIMECMP_OFFSET);
+    assert_eq!(offset_of!(Clint<HARTS>, mtime), MTIME_OFFSET);
+    assert_eq!(
+        offset_of!(Mtimer<HARTS>, mtime),
+        MTIME_OFFSET - MTIMECMP_OFFSET
+    );
+}
+
+#[test]
+fn test() {
+    assert_layout::<1>();
+    assert_layout::<8>();
+    assert_layout::<MAX_HARTS>();
+    assert_eq!(core::mem::size_of::<MSWI>(), MSWI_SIZE);
+    assert_eq!(core::mem::size_of::<SSWI>(), SSWI_SIZE);
+    assert_eq!(core::mem::size_of::<MTIMER>(), MTIMER_SIZE);
+    assert_eq!(core::mem::size_of::<[MTIMECMP; 4095]>(), 0x7ff8);
+    assert_eq!(core::mem::size_of::<SifiveClint>(), SIFIVE_CLINT_SIZE);
+    assert_eq!(SIFIVE_CLINT_SIZE, 0xc000);
+}
+
+#[test]
+fn test_small_clint() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    clint.set_msip(3);
+    clint.write_mtimecmp(3, 0x99);
+    assert!(clint.read_msip(3));
+    assert_eq!(clint.read_mtimecmp(3), 0x99);
+    assert_eq!(clint.try_set_msip(4), Err(InvalidHartId(4)));
+    assert_eq!(Clint::<4>::regions()[2], (16, MSWI_SIZE - 16));
+    let base = &clint as *const _ as usize;
+    assert_eq!(msip_addr(base, 3), &clint.mswi.msip[3] as *const _ as usize);
+    assert_eq!(
+        mtimecmp_addr(base, 3),
+        &clint.mtimecmp[3] as *const _ as usize
+    );
+}
+
+#[test]
+fn test_sswi() {
+    assert_eq!(core::mem::size_of::<SSWI>(), 0x4000);
+    let sswi: SSWI = unsafe { core::mem::zeroed() };
+    sswi.set_ssip(4094);
+    assert!(sswi.read_ssip(4094));
+    assert!(!sswi.read_ssip(0));
+    sswi.clear_ssip(4094);
+    assert!(!sswi.read_ssip(4094));
+}
+
+#[test]
+fn test_from_regions() {
+    static MTIMER_DEVICE: MTIMER = unsafe { core::mem::zeroed() };
+    let base = &MTIMER_DEVICE as *const _ as usize;
+
+    let aclint = unsafe { Aclint::from_regions(None, Some((base, MTIMER_SIZE)), None) }.unwrap();
+    assert!(aclint.mswi.is_none() && aclint.sswi.is_none());
+    aclint.mtimer.unwrap().write_mtimecmp(2, 0x1234);
+    assert_eq!(MTIMER_DEVICE.read_mtimecmp(2), 0x1234);
+
+    let undersized = unsafe { Aclint::from_regions(None, Some((base, 0x4000)), None) };
+    assert_eq!(
+        undersized.err(),
+        Some(AclintError::RegionTooSmall {
+            device: "MTIMER",
+            len: 0x4000,
+            required: MTIMER_SIZE,
+        })
+    );
+}
+
+#[test]
+fn test_from_base() {
+    static MSWI_DEVICE: MSWI = unsafe { core::mem::zeroed() };
+    static MTIMER_DEVICE: MTIMER = unsafe { core::mem::zeroed() };
+    let mswi = unsafe { MSWI::from_base(&MSWI_DEVICE as *const _ as usize) };
+    let mtimer = unsafe { MTIMER::from_base(&MTIMER_DEVICE as *const _ as usize) };
+    mswi.set_msip(3);
+    mtimer.write_mtime(0x55);
+    mtimer.write_mtimecmp(3, 0xaa);
+    assert!(MSWI_DEVICE.read_msip(3));
+    assert_eq!(MTIMER_DEVICE.read_mtime(), 0x55);
+    assert_eq!(MTIMER_DEVICE.read_mtimecmp(3), 0xaa);
+}
+
+#[test]
+fn test_split_access() {
+    // high word carries between the first read and the low read
+    let mut his = [0u32, 1, 1, 1].into_iter();
+    let mut los = [0xffff_fff0u32, 0x10].into_iter();
+    let mut reads = 0;
+    let val = read_split(
+        || {
+            reads += 1;
+            his.next().unwrap()
+        },
+        || los.next().unwrap(),
+    );
+    assert_eq!(val, 0x1_0000_0010);
+    assert_eq!(reads, 4);
+
+    let mut writes = [(false, 0); 3];
+    let log = core::cell::RefCell::new(writes.iter_mut());
+    write_split(
+        |v| *log.borrow_mut().next().unwrap() = (false, v),
+        |v| *log.borrow_mut().next().unwrap() = (true, v),
+        0x12_3456_789a,
+    );
+    assert_eq!(
+        writes,
+        [(false, u32::MAX), (true, 0x12), (false, 0x3456_789a)]
+    );
+}
+
+#[test]
+fn test_min_representable_nanos() {
+    assert_eq!(TimebaseFrequency(10_000_000).min_representable_nanos(), 100);
+    assert_eq!(TimebaseFrequency(3_000_000).min_representable_nanos(), 334);
+    assert_eq!(TimebaseFrequency(0).min_representable_nanos(), u64::MAX);
+}
+
+#[test]
+fn test_read_mtimecmp_checked() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    clint.write_mtimecmp(4094, 0x1234);
+    assert_eq!(clint.read_mtimecmp_checked(4094), Some(0x1234));
+    assert_eq!(clint.read_mtimecmp_checked(4095), None);
+    assert_eq!(clint.read_mtimecmp_checked(usize::MAX), None);
+}
+
+#[test]
+fn test_try_accessors() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    assert_eq!(clint.try_set_msip(7), Ok(()));
+    assert_eq!(clint.try_read_msip(7), Ok(true));
+    assert_eq!(clint.try_clear_msip(7), Ok(()));
+    assert_eq!(clint.try_read_msip(7), Ok(false));
+    assert_eq!(clint.try_write_mtimecmp(4094, 0x42), Ok(()));
+    assert_eq!(clint.try_read_mtimecmp(4094), Ok(0x42));
+    for bad in [4095, 5000, usize::MAX] {
+        assert_eq!(clint.try_set_msip(bad), Err(InvalidHartId(bad)));
+        assert_eq!(clint.try_clear_msip(bad), Err(InvalidHartId(bad)));
+        assert_eq!(clint.try_read_msip(bad), Err(InvalidHartId(bad)));
+        assert_eq!(clint.try_write_mtimecmp(bad, 1), Err(InvalidHartId(bad)));
+        assert_eq!(clint.try_read_mtimecmp(bad), Err(InvalidHartId(bad)));
+    }
+}
+
+#[test]
+fn test_clint_from_base() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let base = &clint as *const _ as usize;
+    let handle = unsafe { SifiveClint::from_base(base) };
+    assert_eq!(handle.base_address(), base);
+    handle.write_mtimecmp(1, 0x77);
+    assert_eq!(clint.read_mtimecmp(1), 0x77);
+}
+
+#[test]
+fn test_validate_mask() {
+    assert_eq!(
+        validate_mask(&HartMask::from_mask_base(0b1011, 0), 4),
+        Ok(())
+    );
+    assert_eq!(
+        validate_mask(&HartMask::from_mask_base(0b1, 4094), MAX_HARTS),
+        Ok(())
+    );
+    assert_eq!(
+        validate_mask(&HartMask::from_mask_base(!0, usize::MAX), 4),
+        Ok(())
+    );
+    assert_eq!(
+        validate_mask(&HartMask::from_mask_base(0b1_0010, 2), 4),
+        Err(AclintError::HartOutOfRange {
+            hart_id: 6,
+            capacity: 4,
+        })
+    );
+}
+
+#[test]
+fn test_hexdump() {
+    struct Buf {
+        bytes: [u8; 256],
+        len: usize,
+    }
+    impl core::fmt::Write for Buf {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(core::fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    clint.write_mtime(0xabc);
+    clint.write_mtimecmp(0, u64::MAX);
+    clint.write_mtimecmp(1, 0x1234);
+    clint.set_msip(1);
+    let mut buf = Buf {
+        bytes: [0; 256],
+        len: 0,
+    };
+    // more harts than the device has are cut off at `HARTS`
+    clint.hexdump(&mut buf, 5000).unwrap();
+    assert_eq!(
+        core::str::from_utf8(&buf.bytes[..buf.len]).unwrap(),
+        "mtime 0x0000000000000abc\n\
+         hart mtimecmp           msip\n\
+         \x20  0 0xffffffffffffffff 0\n\
+         \x20  1 0x0000000000001234 1\n\
+         \x20  2 0x0000000000000000 0\n\
+         \x20  3 0x0000000000000000 0\n"
+    );
+}
+
+#[test]
+fn test_soonest_deadline() {
+    let clint: Clint<8> = unsafe { core::mem::zeroed() };
+    clint.restore_timers(&[u64::MAX; 8]);
+    assert_eq!(clint.soonest_deadline(8), None);
+
+    clint.write_mtime(u64::MAX - 100);
+    clint.write_mtimecmp(1, 500);
+    clint.write_mtimecmp(3, u64::MAX - 10);
+    clint.write_mtimecmp(6, 20);
+    // hart 3 comes first even though hart 6 has the smaller value past the wraparound
+    assert_eq!(clint.soonest_deadline(8), Some(u64::MAX - 10));
+    // harts beyond `hart_count` are not considered
+    assert_eq!(clint.soonest_deadline(2), Some(500));
+}
+
+#[test]
+fn test_compare_deadlines() {
+    use core::cmp::Ordering::*;
+    assert_eq!(compare_deadlines(10, 20, 0), Less);
+    assert_eq!(compare_deadlines(20, 10, 0), Greater);
+    assert_eq!(compare_deadlines(7, 7, 3), Equal);
+    // `a` is numerically smaller but comes after the wraparound, so it is later
+    let now = u64::MAX - 50;
+    assert_eq!(compare_deadlines(5, u64::MAX - 1, now), Greater);
+    // a deadline that already passed is earlier than one still ahead
+    assert_eq!(compare_deadlines(now - 1, now + 1, now), Less);
+}
+
+#[test]
+fn test_nth_period_deadline() {
+    assert_eq!(nth_period_deadline(0, 100, 1000), 100_000);
+    assert_eq!(nth_period_deadline(u64::MAX - 49, 100, 1), 50);
+}
+
+#[test]
+fn test_addr() {
+    use core::mem::offset_of;
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let base = &clint as *const _ as usize;
+
+    assert_eq!(offset_of!(SifiveClint, mswi), MSIP_OFFSET);
+    assert_eq!(offset_of!(SifiveClint, mtimecmp), MTIMECMP_OFFSET);
+    assert_eq!(offset_of!(SifiveClint, mtime), MTIME_OFFSET);
+    assert_eq!(msip_addr(base, 7), &clint.mswi.msip[7] as *const _ as usize);
+    assert_eq!(
+        mtimecmp_addr(base, 4094),
+        &clint.mtimecmp[4094] as *const _ as usize
+    );
+    assert_eq!(mtime_addr(base), &clint.mtime as *const _ as usize);
+}
+
+#[test]
+fn test_regions() {
+    use core::mem::{offset_of, size_of};
+    let [mswi, timer, reserved] = SifiveClint::regions();
+
+    assert_eq!(
+        mswi,
+        (offset_of!(SifiveClint, mswi), size_of::<[MSIP; 4095]>())
+    );
+    assert_eq!(timer.0, offset_of!(SifiveClint, mtimecmp));
+    assert_eq!(timer.1, size_of::<MTIMER>());
+    assert_eq!(
+        timer.0 + timer.1,
+        offset_of!(SifiveClint, mtime) + size_of::<MTIME>()
+    );
+    assert_eq!(reserved, (offset_of!(MSWI, _reserved), size_of::<u32>()));
+    let total: usize = SifiveClint::regions().iter().map(|&(_, len)| len).sum();
+    assert_eq!(total, SIFIVE_CLINT_SIZE);
+}
+
+#[test]
+fn test_sync() {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<MSWI>();
+    assert_sync::<SSWI>();
+    assert_sync::<MTIMER>();
+    assert_sync::<SifiveClint>();
+    assert_sync::<&'static SifiveClint>();
+}
+
+#[test]
+fn test_wait() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    assert!(!clint.wait_msip(1, 16));
+    clint.set_msip(1);
+    assert!(clint.wait_msip(1, DEFAULT_MAX_SPINS));
+
+    clint.write_mtimecmp(1, 100);
+    clint.write_mtime(99);
+    assert!(!clint.wait_timer(1, 16));
+    clint.write_mtime(100);
+    assert!(clint.wait_timer(1, DEFAULT_MAX_SPINS));
+}
+
+#[test]
+fn test_split() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let (mswi, mtimer) = clint.split();
+
+    mswi.set_msip(3);
+    assert!(clint.read_msip(3));
+    clint.clear_msip(3);
+    assert!(!mswi.read_msip(3));
+
+    mtimer.write_mtime(0x1234);
+    assert_eq!(clint.read_mtime(), 0x1234);
+    clint.write_mtimecmp(4094, 0x5678);
+    assert_eq!(mtimer.read_mtimecmp(4094), 0x5678);
+}
+
+#[test]
+fn test_monotonic_ticks() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let mut ticks = MonotonicTicks::new();
+
+    clint.write_mtime(100);
+    assert_eq!(ticks.update(&clint), 100);
+    clint.write_mtime(u64::MAX - 1);
+    assert_eq!(ticks.update(&clint), u64::MAX as u128 - 1);
+    // MTIME wraps around
+    clint.write_mtime(5);
+    assert_eq!(ticks.update(&clint), (1 << 64) + 5);
+    clint.write_mtime(6);
+    assert_eq!(ticks.update(&clint), (1 << 64) + 6);
+}
+
+#[test]
+fn test_armed_timers() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    for i in 0..8 {
+        clint.write_mtimecmp(i, u64::MAX);
+    }
+    clint.write_mtimecmp(2, 200);
+    clint.write_mtimecmp(5, 500);
+    // out of `hart_count`
+    clint.write_mtimecmp(8, 800);
+
+    let mut armed = clint.armed_timers(8);
+    assert_eq!(armed.next(), Some((2, 200)));
+    assert_eq!(armed.next(), Some((5, 500)));
+    assert_eq!(armed.next(), None);
+}
+
+#[test]
+fn test_timer_queue() {
+    use core::cell::Cell;
+
+    struct Scripted<'a>(Cell<&'a [u64]>);
+
+    impl TimeRead for Scripted<'_> {
+        fn now(&self) -> u64 {
+            let (first, rest) = self.0.get().split_first().unwrap();
+            self.0.set(rest);
+            *first
+        }
+    }
+
+    let mut queue = TimerQueue::<4>::new();
+    assert_eq!(queue.peek(), None);
+    // an empty queue does not read the clock
+    assert_eq!(queue.pop_expired(&Scripted(Cell::new(&[]))), None);
+
+    // kept in deadline order, ties in push order, across the wraparound of MTIME
+    for (deadline, token) in [(30, 0), (u64::MAX - 5, 1), (10, 2), (30, 3)] {
+        assert_eq!(queue.push(deadline, token), Ok(()));
+    }
+    assert_eq!(queue.push(20, 4), Err((20, 4)));
+    assert_eq!(queue.len(), 4);
+    assert_eq!(queue.peek(), Some(u64::MAX - 5));
+
+    // one sample per call; nothing is popped before its deadline
+    let clock = Scripted(Cell::new(&[u64::MAX - 6, u64::MAX - 5, 9, 30, 30, 30, 29]));
+    assert_eq!(queue.pop_expired(&clock), None);
+    assert_eq!(queue.pop_expired(&clock), Some((u64::MAX - 5, 1)));
+    assert_eq!(queue.pop_expired(&clock), None);
+    assert_eq!(queue.pop_expired(&clock), Some((10, 2)));
+    assert_eq!(queue.pop_expired(&clock), Some((30, 0)));
+    assert_eq!(queue.pop_expired(&clock), Some((30, 3)));
+    assert!(queue.is_empty());
+    assert_eq!(queue.pop_expired(&clock), None);
+    assert_eq!(clock.0.get(), &[29]);
+}
+
+#[test]
+fn test_mtime_is_running() {
+    use core::cell::Cell;
+
+    struct Ticking(Cell<u64>);
+
+    impl TimeRead for Ticking {
+        fn now(&self) -> u64 {
+            self.0.set(self.0.get() + 1);
+            self.0.get()
+        }
+    }
+
+    assert!(is_running(&Ticking(Cell::new(0)), 16));
+    let frozen: SifiveClint = unsafe { core::mem::zeroed() };
+    frozen.write_mtime(42);
+    assert!(!frozen.mtime_is_running(16));
+}
+
+#[test]
+fn test_pending_interrupts() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    for hart in 0..4 {
+        clint.write_mtimecmp(hart, u64::MAX);
+    }
+    clint.write_mtime(100);
+    clint.set_msip(0);
+    clint.write_mtimecmp(1, 100);
+    clint.set_msip(2);
+    clint.write_mtimecmp(2, 50);
+    clint.write_mtimecmp(3, 101);
+
+    let mut pending = clint.pending_interrupts(4);
+    assert_eq!(pending.next(), Some((0, IntKind::Software)));
+    assert_eq!(pending.next(), Some((1, IntKind::Timer)));
+    assert_eq!(pending.next(), Some((2, IntKind::Software)));
+    assert_eq!(pending.next(), Some((2, IntKind::Timer)));
+    assert_eq!(pending.next(), None);
+    // zeroed MTIMECMP of the remaining harts count as fired
+    assert_eq!(clint.pending_interrupts(usize::MAX).count(), 4 + 4091);
+}
+
+#[test]
+fn test_pending_count() {
+    let mswi: MSWI = unsafe { core::mem::zeroed() };
+    mswi.set_msip(0);
+    mswi.set_msip(4);
+    mswi.set_msip(7);
+    mswi.set_msip(4094);
+    assert_eq!(mswi.pending_count(8), 3);
+    assert_eq!(mswi.pending_count(usize::MAX), 4);
+}
+
+#[test]
+fn test_apply_states() {
+    let mswi: MSWI = unsafe { core::mem::zeroed() };
+    mswi.set_msip(1);
+    mswi.set_msip(5);
+    let states = [true, false, true, false, true];
+    mswi.apply_states(&states);
+    for (i, &pending) in states.iter().enumerate() {
+        assert_eq!(mswi.read_msip(i), pending);
+    }
+    // harts past the array keep their state
+    assert!(mswi.read_msip(5));
+    mswi.apply_states(&[true; 5000]);
+    assert_eq!(mswi.pending_count(usize::MAX), 4095);
+}
+
+#[test]
+fn test_align_timer_to() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    clint.write_mtimecmp(0, 0x1000);
+    clint.write_mtimecmp(3, u64::MAX);
+    assert_eq!(clint.align_timer_to(3, 0), Ok(0x1000));
+    assert_eq!(clint.read_mtimecmp(3), 0x1000);
+    assert_eq!(clint.read_mtimecmp(0), 0x1000);
+
+    // out-of-range harts are rejected and leave every deadline alone
+    assert_eq!(clint.align_timer_to(4095, 0), Err(InvalidHartId(4095)));
+    assert_eq!(clint.align_timer_to(3, 4095), Err(InvalidHartId(4095)));
+    assert_eq!(
+        clint.align_timer_to(usize::MAX, 4095),
+        Err(InvalidHartId(usize::MAX))
+    );
+    clint.write_mtimecmp(0, 0x2000);
+    assert_eq!(
+        clint.align_timer_to(3, usize::MAX),
+        Err(InvalidHartId(usize::MAX))
+    );
+    assert_eq!(clint.read_mtimecmp(3), 0x1000);
+}
+
+#[test]
+fn test_save_restore_timers() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let deadlines = [0x10, u64::MAX, 0, 0x1234_5678_9abc];
+    clint.restore_timers(&deadlines);
+    let mut saved = [1; 4];
+    clint.save_timers(&mut saved);
+    assert_eq!(saved, deadlines);
+
+    clint.restore_timers(&[7; 4]);
+    clint.restore_timers(&saved);
+    clint.save_timers(&mut saved);
+    assert_eq!(saved, deadlines);
+    // oversized slices stop at the last hart
+    let mut big = [0; 5000];
+    clint.save_timers(&mut big);
+    assert_eq!(big[..4], deadlines);
+    clint.restore_timers(&[3; 5000]);
+    assert_eq!(clint.read_mtimecmp(4094), 3);
+}
+
+#[test]
+fn test_arm_verified() {
+    use core::cell::Cell;
+
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    assert_eq!(clint.arm_verified(3, 0x1234), Ok(()));
+    assert_eq!(clint.read_mtimecmp(3), 0x1234);
+    // disarming reads back `u64::MAX` and is not mistaken for an error
+    assert_eq!(clint.arm_verified(2, u64::MAX), Ok(()));
+    assert_eq!(clint.read_mtimecmp(2), u64::MAX);
+    assert_eq!(
+        clint.arm_verified(4, 0x1234),
+        Err(ArmError::InvalidHart(InvalidHartId(4)))
+    );
+    assert_eq!(
+        clint.arm_verified(usize::MAX, 0x1234),
+        Err(ArmError::InvalidHart(InvalidHartId(usize::MAX)))
+    );
+    assert_eq!(clint.read_mtimecmp(0), 0);
+
+    // a read-only register keeps its stale value
+    let stale = Cell::new(0x5678);
+    let write = || {};
+    assert_eq!(verify_write(write, || stale.get(), 0x1234), Err(0x5678));
+    let write = || stale.set(0x1234);
+    assert_eq!(verify_write(write, || stale.get(), 0x1234), Ok(()));
+}
+
+#[test]
+fn test_arm_all_checked() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    assert_eq!(clint.arm_all_checked(3, 0x1234), 3);
+    assert_eq!(
+        [0, 1, 2, 3].map(|hart_idx| clint.read_mtimecmp(hart_idx)),
+        [0x1234, 0x1234, 0x1234, 0]
+    );
+    // counts stop at the harts the device covers
+    assert_eq!(clint.arm_all_checked(5000, 0x5678), 4);
+    assert_eq!(
+        [0, 1, 2, 3].map(|hart_idx| clint.read_mtimecmp(hart_idx)),
+        [0x5678; 4]
+    );
+    assert_eq!(clint.arm_all_checked(0, 0x9abc), 0);
+    assert_eq!(clint.read_mtimecmp(0), 0x5678);
+
+    // host memory can't be mapped read-only here, so harts 1 and 3 fail the read-back
+    // the way a read-only register would, and the rest are armed for real
+    let read_only = |hart_idx| hart_idx == 1 || hart_idx == 3;
+    let armed = clint.arm_all_with(5000, |hart_idx| {
+        if read_only(hart_idx) {
+            Err(ArmError::Mismatch(clint.read_mtimecmp(hart_idx)))
+        } else {
+            clint.arm_verified(hart_idx, 0x9abc)
+        }
+    });
+    assert_eq!(armed, 2);
+    assert_eq!(
+        [0, 1, 2, 3].map(|hart_idx| clint.read_mtimecmp(hart_idx)),
+        [0x9abc, 0x5678, 0x9abc, 0x5678]
+    );
+}
+
+#[test]
+fn test_reset_hart_state() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    clint.set_msip(2);
+    clint.write_mtimecmp(2, u64::MAX);
+    clint.set_msip(3);
+    clint.write_mtimecmp(3, 0x1234);
+    clint.reset_hart_state(2);
+    assert!(!clint.read_msip(2));
+    assert_eq!(clint.read_mtimecmp(2), 0);
+    assert!(clint.read_msip(3));
+    assert_eq!(clint.read_mtimecmp(3), 0x1234);
+}
+
+#[test]
+fn test_load_mtime() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    clint.write_mtime(0x1234);
+    for order in [Ordering::Relaxed, Ordering::Acquire, Ordering::SeqCst] {
+        assert_eq!(clint.load_mtime(order), 0x1234);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_load_mtime_release() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    clint.load_mtime(Ordering::Release);
+}
+
+#[test]
+fn test_arm_jittered() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let (base, jitter) = (1_000_000, 5_000);
+    let mut deadlines = [0; 8];
+    for (hart, deadline) in deadlines.iter_mut().enumerate() {
+        *deadline = clint.arm_jittered(hart, base, jitter, 42);
+        assert!((base..base + jitter).contains(deadline));
+        assert_eq!(clint.read_mtimecmp(hart), *deadline);
+        assert_eq!(clint.arm_jittered(hart, base, jitter, 42), *deadline);
+    }
+    assert!(deadlines.iter().any(|&d| d != deadlines[0]));
+    assert_eq!(clint.arm_jittered(0, base, 0, 42), base);
+}
+
+#[test]
+fn test_pull_in_mtimecmp() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    clint.write_mtimecmp(0, u64::MAX);
+    assert_eq!(clint.pull_in_mtimecmp(0, 100), 100);
+    assert_eq!(clint.pull_in_mtimecmp(0, 200), 100);
+    assert_eq!(clint.pull_in_mtimecmp(0, 50), 50);
+    assert_eq!(clint.read_mtimecmp(0), 50);
+
+    // 5 lies after the wraparound, so the numerically larger candidate is earlier
+    clint.write_mtimecmp(1, 5);
+    assert_eq!(clint.pull_in_mtimecmp(1, u64::MAX - 10), u64::MAX - 10);
+    // and the other way around, 5 is later than `u64::MAX - 10`
+    assert_eq!(clint.pull_in_mtimecmp(1, 5), u64::MAX - 10);
+}
+
+#[test]
+fn test_rollover_seconds() {
+    let freq = TimebaseFrequency(10_000_000);
+    assert_eq!(freq.rollover_seconds_for(32), 429);
+    assert_eq!(rollover_seconds(freq), 1_844_674_407_370);
+    assert_eq!(freq.rollover_seconds_for(64), 1_844_674_407_370);
+    assert_eq!(freq.rollover_seconds_for(128), 1_844_674_407_370);
+    assert_eq!(freq.rollover_seconds_for(u32::MAX), 1_844_674_407_370);
+    // 2^64 seconds at 1 Hz do not fit
+    assert_eq!(TimebaseFrequency(1).rollover_seconds_for(64), u64::MAX);
+    assert_eq!(rollover_seconds(TimebaseFrequency(0)), u64::MAX);
+}
+
+#[cfg(feature = "inline-always")]
+#[test]
+fn test_inline_always() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let (mswi, mtimer) = clint.split();
+    mswi.set_msip(0);
+    assert!(clint.read_msip(0));
+    mswi.clear_msip(0);
+    mtimer.write_mtime(1);
+    mtimer.write_mtimecmp(0, 2);
+    assert_eq!(clint.now() + 1, clint.read_mtimecmp(0));
+}
+
+#[cfg(feature = "duration")]
+#[test]
+fn test_time_until() {
+    let clint: SifiveClint = unsafe { core::mem::zeroed() };
+    let freq = TimebaseFrequency(10_000_000);
+    clint.write_mtime(1_000);
+
+    assert_eq!(clint.time_until(999, freq), None);
+    assert_eq!(clint.time_until(1_000, freq), Some(Duration::ZERO));
+    assert_eq!(
+        clint.time_until(1_000 + 15_000_001, freq),
+        Some(Duration::new(1, 500_000_100))
+    );
+    assert_eq!(clint.time_until(2_000, TimebaseFrequency(0)), None);
+    // the deadline lies past the wraparound
+    clint.write_mtime(u64::MAX - 9);
+    assert_eq!(
+        clint.time_until(10, freq),
+        Some(Duration::from_nanos(2_000))
+    );
+}
+
+#[cfg(feature = "trace-ring")]
+#[test]
+fn test_trace_ring() {
+    let ring = TraceRing::<4>::new();
+    assert_eq!(ring.recent().count(), 0);
+    for i in 0..6 {
+        ring.record(MmioAccess {
+            addr: 0x200_0000 + i * 4,
+            value: i as u64,
+            write: i % 2 == 0,
+        });
+    }
+    let mut recent = ring.recent();
+    for i in 2..6 {
+        let access = recent.next().unwrap();
+        assert_eq!((access.addr, access.value), (0x200_0000 + i * 4, i as u64));
+        assert_eq!(access.write, i % 2 == 0);
+    }
+    assert_eq!(recent.next(), None);
+}
+
+#[cfg(feature = "riscv")]
+#[test]
+fn test_arm_timer_guarded() {
+    let clint: Clint<4> = unsafe { core::mem::zeroed() };
+    {
+        let _guard = clint.arm_timer_guarded(1, 0x1234);
+        assert_eq!(clint.read_mtimecmp(1), 0x1234);
+    }
+    assert_eq!(clint.read_mtimecmp(1), u64::MAX);
+    assert_eq!(clint.read_mtimecmp(0), 0);
+}
+
+#[cfg(feature = "riscv")]
+#[test]
+fn test_my_pending_compiles() {
+    // `mhartid` cannot be read on the host; only check the signature.
+    let _: fn(&SifiveClint) -> (bool, bool) = SifiveClint::my_pending;
 }
@@ -0,0 +1,110 @@
+//! Ring buffer of recent register accesses, enabled by the `trace-ring` feature.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering, fence};
+
+/// One register access.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MmioAccess {
+    /// Address of the register.
+    pub addr: usize,
+    /// Value read or written, zero-extended.
+    pub value: u64,
+    /// Whether the access is a write.
+    pub write: bool,
+}
+
+/// Number of accesses kept in [`TRACE_RING`].
+pub const TRACE_RING_LEN: usize = 64;
+
+/// Accesses made through this crate's register accessors.
+pub static TRACE_RING: TraceRing<TRACE_RING_LEN> = TraceRing::new();
+
+/// Fixed-size ring keeping the last `N` accesses.
+///
+/// Recording takes no lock. Each slot is a set of atomics guarded by a sequence word, so
+/// concurrent use from several harts is safe, and [`recent`](Self::recent) skips a slot
+/// that is being written or has been overwritten meanwhile. Two harts writing the same slot
+/// at once may still leave a mixed record; it is meant to be read after a fault, once other
+/// harts are quiet.
+pub struct TraceRing<const N: usize> {
+    next: AtomicUsize,
+    slots: [Slot; N],
+}
+
+/// One slot of a [`TraceRing`]. `seq` is the record number plus one once the record is
+/// complete, and 0 while it is being written.
+struct Slot {
+    seq: AtomicUsize,
+    addr: AtomicUsize,
+    lo: AtomicU32,
+    hi: AtomicU32,
+    write: AtomicBool,
+}
+
+impl<const N: usize> TraceRing<N> {
+    /// An empty ring.
+    pub const fn new() -> Self {
+        const { assert!(N > 0) };
+        Self {
+            next: AtomicUsize::new(0),
+            slots: [const {
+                Slot {
+                    seq: AtomicUsize::new(0),
+                    addr: AtomicUsize::new(0),
+                    lo: AtomicU32::new(0),
+                    hi: AtomicU32::new(0),
+                    write: AtomicBool::new(false),
+                }
+            }; N],
+        }
+    }
+
+    /// Append an access, overwriting the oldest one when the ring is full.
+    #[inline]
+    pub fn record(&self, access: MmioAccess) {
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[i % N];
+        slot.seq.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.addr.store(access.addr, Ordering::Relaxed);
+        slot.lo.store(access.value as u32, Ordering::Relaxed);
+        slot.hi
+            .store((access.value >> 32) as u32, Ordering::Relaxed);
+        slot.write.store(access.write, Ordering::Relaxed);
+        slot.seq.store(i.wrapping_add(1), Ordering::Release);
+    }
+
+    /// The last (at most `N`) recorded accesses, oldest first.
+    ///
+    /// A record that is incomplete or replaced by a newer one while reading is left out.
+    pub fn recent(&self) -> impl Iterator<Item = MmioAccess> + '_ {
+        let next = self.next.load(Ordering::Acquire);
+        (next.saturating_sub(N)..next).filter_map(move |i| {
+            let slot = &self.slots[i % N];
+            let seq = i.wrapping_add(1);
+            if slot.seq.load(Ordering::Acquire) != seq {
+                return None;
+            }
+            let access = MmioAccess {
+                addr: slot.addr.load(Ordering::Relaxed),
+                value: slot.lo.load(Ordering::Relaxed) as u64
+                    | (slot.hi.load(Ordering::Relaxed) as u64) << 32,
+                write: slot.write.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            (slot.seq.load(Ordering::Relaxed) == seq).then_some(access)
+        })
+    }
+}
+
+impl<const N: usize> Default for TraceRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Record an access in [`TRACE_RING`].
+#[inline(always)]
+pub(crate) fn record(addr: usize, value: u64, write: bool) {
+    TRACE_RING.record(MmioAccess { addr, value, write });
+}
@@ -28,7 +28,9 @@ SECTIONS {
         *(.sdata .sdata.*)
     } > DRAM
     .bss (NOLOAD) : {
+        sstack = .;
         *(.bss.uninit)
+        estack = .;
         . = ALIGN(8);
         sbss = .;
         *(.bss .bss.*)
@@ -36,6 +38,9 @@ SECTIONS {
         . = ALIGN(8);
         ebss = .;
     } > DRAM
+    .sentinel (NOLOAD) : ALIGN(8) {
+        *(.sentinel)
+    } > DRAM
     /DISCARD/ : {
         *(.eh_frame)
     }
@@ -0,0 +1,91 @@
+use crate::{
+    suite::{Env, TestOutcome},
+    BoardInfo,
+};
+use aclint::{SifiveClint, DEFAULT_MAX_SPINS, MAX_HARTS};
+
+/// 检查 `clint` 确实映射到一个正在计时的 CLINT：自旋期间 MTIME 必须前进。
+pub(crate) fn clint_responds(clint: &SifiveClint) -> bool {
+    clint.mtime_is_running(DEFAULT_MAX_SPINS)
+}
+
+/// 启动时检查本核的 MTIMECMP，不是 `u64::MAX` 说明固件或之前的引导程序留下了一个时钟，
+/// 打开中断后可能意外触发。必须在打开中断之前调用。
+pub(crate) fn warn_armed_timer(clint: &SifiveClint, hartid: usize) {
+    let mtimecmp = clint.read_mtimecmp(hartid);
+    if mtimecmp != u64::MAX {
+        note!(
+            "WARNING: mtimecmp of hart {hartid} is armed at {mtimecmp:#x} on boot, mtime is {:#x}",
+            clint.read_mtime()
+        );
+    }
+}
+
+/// 映射设备树给出的 CLINT，并且只在确认 MTIME 前进时返回。
+///
+/// 直接访问 CLINT 的测试都应通过这个函数取得 CLINT，而不是各自检查。
+pub(crate) fn verified_clint(board: &BoardInfo) -> Option<&'static SifiveClint> {
+    if board.clint == 0 {
+        note!("CLINT not found in device tree");
+        return None;
+    }
+    // S 态未开启分页，物理地址即虚拟地址
+    let clint = unsafe { SifiveClint::from_base(board.clint) };
+    if clint_responds(clint) {
+        Some(clint)
+    } else {
+        note!(
+            "CLINT at {:#x} ignored: MTIME does not advance",
+            board.clint
+        );
+        None
+    }
+}
+
+/// 按设备树给出的硬件线程号（而不是 `0..smp`）逐个访问 CLINT。
+///
+/// 超出 CLINT 容量的硬件线程号会打印警告并跳过。
+pub(crate) fn for_each_hart(
+    board: &BoardInfo,
+    clint: &SifiveClint,
+    f: impl FnMut(usize, &SifiveClint),
+) {
+    for_each_id(board.hart_ids(), clint, f)
+}
+
+fn for_each_id(ids: &[usize], clint: &SifiveClint, mut f: impl FnMut(usize, &SifiveClint)) {
+    for &hart in ids {
+        if hart < MAX_HARTS {
+            f(hart, clint);
+        } else {
+            diag!("WARNING: hart {hart} is beyond CLINT capacity, skipped");
+        }
+    }
+}
+
+/// 确认 [`for_each_hart`] 访问设备树中的每个硬件线程，并能处理不连续和越界的硬件线程号。
+pub(crate) fn test_for_each_hart(env: &Env) -> TestOutcome {
+    let Some(clint) = env.clint else {
+        return TestOutcome::Skip;
+    };
+    let mut visited = 0;
+    for_each_hart(env.board, clint, |hart, clint| {
+        diag!("hart {hart}: mtimecmp {:#x}", clint.read_mtimecmp(hart));
+        visited += 1;
+    });
+    let real_ok = visited == env.board.hart_ids().len();
+
+    const SPARSE: [usize; 4] = [0, 3, MAX_HARTS, 7];
+    let mut seen = [usize::MAX; SPARSE.len()];
+    let mut count = 0;
+    for_each_id(&SPARSE, clint, |hart, _| {
+        seen[count] = hart;
+        count += 1;
+    });
+    let sparse_ok = seen[..count] == [0, 3, 7];
+    if real_ok && sparse_ok {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
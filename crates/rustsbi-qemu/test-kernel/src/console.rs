@@ -0,0 +1,147 @@
+use crate::{
+    sbi,
+    suite::{Env, TestOutcome},
+    uart,
+};
+use aclint::DEFAULT_MAX_SPINS;
+
+/// 通过 DBCN 写一个字节，再从串口读回，确认固件的控制台和内核用的是同一个串口。
+///
+/// 只有串口配置为回环时才有意义，因此需要打开 `dbcn-loopback` 特性。
+pub(crate) fn test_dbcn_loopback(_env: &Env) -> TestOutcome {
+    if !cfg!(feature = "dbcn-loopback") {
+        return TestOutcome::Skip;
+    }
+    require_ext!(DBCN, "dbcn_loopback");
+    const BYTE: u8 = 0x5a;
+    let uart = uart();
+    // 丢弃之前残留的输入
+    let mut c = 0u8;
+    while uart.read(core::slice::from_mut(&mut c)) == 1 {}
+
+    if let Err(e) = sbi::console_write_byte(BYTE).result() {
+        diag!("console_write_byte failed with error {e}");
+        return TestOutcome::Fail;
+    }
+    for _ in 0..DEFAULT_MAX_SPINS {
+        if uart.read(core::slice::from_mut(&mut c)) == 1 {
+            diag!("sent {BYTE:#04x}, received {c:#04x}");
+            return if c == BYTE {
+                TestOutcome::Pass
+            } else {
+                TestOutcome::Fail
+            };
+        }
+        core::hint::spin_loop();
+    }
+    diag!("sent {BYTE:#04x}, received nothing");
+    TestOutcome::Fail
+}
+
+const PAGE_SIZE: usize = 4096;
+
+/// 页对齐、跨越多页的缓冲区。
+#[repr(align(4096))]
+struct Pages([u8; 2 * PAGE_SIZE]);
+
+static mut PAGES: Pages = Pages([0; 2 * PAGE_SIZE]);
+
+/// 用一次 `console_write` 输出跨越两页的缓冲区，确认 RustSBI 报告写出了全部字节。
+///
+/// 内容是空格，每 64 字节一个回车，不会在终端上留下可见的输出。
+pub(crate) fn test_dbcn_write_pages(_env: &Env) -> TestOutcome {
+    require_ext!(DBCN, "dbcn_write_pages");
+    // DBCN 从 SBI 2.0 开始定义
+    if !sbi::info().spec_at_least(2, 0) {
+        diag!("SKIP dbcn_write_pages: SBI specification before v2.0");
+        return TestOutcome::Skip;
+    }
+    let pages = &raw mut PAGES;
+    let pages = unsafe { &mut (*pages).0 };
+    for line in pages.chunks_mut(64) {
+        line.fill(b' ');
+        line[63] = b'\r';
+    }
+    let ret = sbi::console_write(pages);
+    match ret.result() {
+        Ok(written) => {
+            diag!("wrote {written} of {} bytes", pages.len());
+            if written == pages.len() {
+                TestOutcome::Pass
+            } else {
+                TestOutcome::Fail
+            }
+        }
+        Err(e) => {
+            diag!("console_write failed with error {e}");
+            TestOutcome::Fail
+        }
+    }
+}
+
+/// 通过 DBCN 读取已有的输入，再用 `console_write` 原样写回，确认读到的字节数合理。
+///
+/// 没有输入时读到 0 字节，同样算通过。
+pub(crate) fn test_dbcn_echo(_env: &Env) -> TestOutcome {
+    require_ext!(DBCN, "dbcn_echo");
+    let mut buf = [0u8; 64];
+    let read = match sbi::console_read(&mut buf).result() {
+        Ok(read) => read,
+        Err(sbi::ERR_NOT_SUPPORTED) => {
+            diag!("SKIP dbcn_echo: console_read unsupported");
+            return TestOutcome::Skip;
+        }
+        Err(e) => {
+            diag!("console_read failed with error {e}");
+            return TestOutcome::Fail;
+        }
+    };
+    if read > buf.len() {
+        diag!(
+            "console_read reported {read} bytes into {} bytes",
+            buf.len()
+        );
+        return TestOutcome::Fail;
+    }
+    let echoed = match sbi::console_write(&buf[..read]).result() {
+        Ok(echoed) => echoed,
+        Err(e) => {
+            diag!("console_write failed with error {e}");
+            return TestOutcome::Fail;
+        }
+    };
+    diag!("read {read} bytes, echoed {echoed}");
+    if echoed == read {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 8 字节对齐的缓冲区，从第 1 字节开始取就得到不对齐的地址。
+#[repr(C, align(8))]
+struct Aligned([u8; 16]);
+
+/// 通过 DBCN 从一个不对齐的物理地址写出字节，确认 RustSBI 正常写出或者返回错误，而不是出错陷入。
+///
+/// 能返回到这里就说明固件没有因为地址不对齐而崩溃，写出的字节数也不能超过缓冲区长度。
+pub(crate) fn test_ecall_edge_args(_env: &Env) -> TestOutcome {
+    require_ext!(DBCN, "ecall_edge_args");
+    let mut buf = Aligned([b'\r'; 16]);
+    buf.0[1..15].fill(b' ');
+    let bytes = &buf.0[1..];
+    let ret = sbi::console_write(bytes);
+    diag!(
+        "address {:p}: error {:#x}, value {}",
+        bytes.as_ptr(),
+        ret.error,
+        ret.value
+    );
+    match ret.result() {
+        Ok(written) if written > bytes.len() => {
+            diag!("wrote {written} bytes from {} bytes", bytes.len());
+            TestOutcome::Fail
+        }
+        _ => TestOutcome::Pass,
+    }
+}
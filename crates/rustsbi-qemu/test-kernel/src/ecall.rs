@@ -0,0 +1,239 @@
+use crate::{
+    irq::{IrqSource, IRQS},
+    sbi,
+    smp::{self, Barrier, MAX_HARTS},
+    suite::{Env, TestOutcome},
+    timer::read_mtime_fenced,
+    trap,
+    watchdog::Watchdog,
+};
+use core::sync::atomic::Ordering;
+use riscv::register::{sip, sstatus, time};
+
+static START: Barrier = Barrier::new();
+
+/// 所有核从屏障同时出发，各自反复调用 `get_spec_version`，
+/// 确认每次都得到启动时探测到的版本，检验 RustSBI 在并发调用下的可重入性。
+pub(crate) fn test_concurrent_ecall(env: &Env) -> TestOutcome {
+    require_ext!(HSM, "concurrent_ecall");
+    if env.board.smp < 2 {
+        diag!("SKIP concurrent_ecall: single hart");
+        return TestOutcome::Skip;
+    }
+    START.reset();
+    if smp::run_on_all_harts(env, spec_version_loop).check(env.board) {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+fn spec_version_loop(env: &Env, hartid: usize) -> bool {
+    const CALLS: usize = 1000;
+    let expected = sbi::info().spec_version;
+    let watchdog = Watchdog::new(env.board.frequency, 1000);
+    if !START.wait(env.board.smp.min(MAX_HARTS), &watchdog) {
+        return false;
+    }
+    for i in 0..CALLS {
+        let version = sbi::get_spec_version();
+        if version != expected {
+            diag!("hart {hartid} call {i}: spec version {version:#x}, expected {expected:#x}");
+            return false;
+        }
+    }
+    true
+}
+
+/// 用 MTIME 给一万次 `get_spec_version` 计时，报告 RustSBI 分发一次调用的开销。
+pub(crate) fn bench_ecall(env: &Env) -> TestOutcome {
+    const BENCH_CALLS: u64 = 10_000;
+    let Some(clint) = env.clint else {
+        return TestOutcome::Skip;
+    };
+    if env.board.frequency == 0 {
+        diag!("SKIP bench_ecall: timebase frequency unknown");
+        return TestOutcome::Skip;
+    }
+    let t0 = read_mtime_fenced(clint);
+    for _ in 0..BENCH_CALLS {
+        core::hint::black_box(sbi::get_spec_version());
+    }
+    let ticks = read_mtime_fenced(clint) - t0;
+    let nanos = ticks as u128 * 1_000_000_000 / env.board.frequency as u128;
+    diag!(
+        "{BENCH_CALLS} calls in {ticks} ticks: {}.{:03} ticks, {} ns per ecall",
+        ticks / BENCH_CALLS,
+        ticks % BENCH_CALLS * 1000 / BENCH_CALLS,
+        nanos / BENCH_CALLS as u128
+    );
+    TestOutcome::Pass
+}
+
+/// 触发 S 态时钟中断，处理函数在中断处理过程中调用 `set_timer` 撤销时钟，
+/// 确认 RustSBI 正确处理陷入处理中的 SBI 调用：调用返回成功，STIP 被清除，处理函数正常返回。
+pub(crate) fn test_ecall_from_handler(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "ecall_from_handler");
+    let before = trap::STIMER.load(Ordering::Relaxed);
+    trap::TIMER_ECALL_ERROR.store(usize::MAX, Ordering::Relaxed);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(time::read64());
+    let watchdog = Watchdog::new(env.board.frequency, 100);
+    unsafe { sstatus::set_sie() };
+    while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+
+    let handled = trap::STIMER.load(Ordering::Acquire) != before;
+    let error = trap::TIMER_ECALL_ERROR.load(Ordering::Relaxed);
+    let stip = sip::read().stimer();
+    diag!("handler completed {handled}, inner set_timer error {error:#x}, STIP {stip}");
+    if handled && error == 0 && !stip {
+        TestOutcome::Pass
+    } else {
+        sbi::set_timer(u64::MAX);
+        TestOutcome::Fail
+    }
+}
+
+/// 调用一个固件没有实现的厂商扩展，确认返回 `ERR_NOT_SUPPORTED`。
+///
+/// 测试自己的厂商扩展时，换成对应的 EID。
+pub(crate) fn test_vendor_noop(_env: &Env) -> TestOutcome {
+    const EID: usize = 0x09FF_FFFF;
+    if sbi::probe_extension(EID) {
+        diag!("SKIP vendor_noop: vendor extension {EID:#x} implemented");
+        return TestOutcome::Skip;
+    }
+    let Some(ret) = sbi::vendor_call(EID, 0, [0; 6]) else {
+        diag!("{EID:#x} is not a vendor EID");
+        return TestOutcome::Fail;
+    };
+    diag!("vendor extension {EID:#x} returned error {:#x}", ret.error);
+    if sbi::vendor_call(sbi::eid::BASE, 0, [0; 6]).is_some() {
+        diag!("vendor_call accepted the base extension");
+        return TestOutcome::Fail;
+    }
+    if ret.error == sbi::ERR_NOT_SUPPORTED {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 确认 [`sbi::machine_ids`] 和分别调用基础扩展的三个功能得到的编号一致，也和启动时探测的一致。
+pub(crate) fn test_machine_ids(_env: &Env) -> TestOutcome {
+    let ids = sbi::machine_ids();
+    let mut single = [0; 3];
+    for (fid, id) in (4..).zip(&mut single) {
+        match sbi::ecall(sbi::eid::BASE, fid, [0; 6]).result() {
+            Ok(value) => *id = value,
+            Err(e) => {
+                diag!("base function {fid} failed with error {e}");
+                return TestOutcome::Fail;
+            }
+        }
+    }
+    let [mvendorid, marchid, mimpid] = single;
+    let single = sbi::MachineIds {
+        mvendorid,
+        marchid,
+        mimpid,
+    };
+    diag!("machine_ids {ids:x?}, single calls {single:x?}");
+    if ids == single && ids == sbi::info().ids {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 发起一次扩展调用。
+type Call = fn(&Env) -> sbi::SbiRet;
+
+/// 各扩展的名称和一个没有副作用的调用。SRST 没有这样的调用，不在其中。
+const BENIGN_CALLS: [(usize, &str, Call); 7] = {
+    use sbi::eid::*;
+    [
+        (TIME, "TIME", |_| sbi::set_timer(u64::MAX)),
+        (SPI, "IPI", |_| sbi::send_ipi(0, 0)),
+        (RFNC, "RFNC", |_| sbi::remote_fence_i(0, 0)),
+        (HSM, "HSM", |env| sbi::hart_get_status(env.hartid)),
+        (PMU, "PMU", |_| sbi::pmu_num_counters()),
+        (DBCN, "DBCN", |_| sbi::console_write(&[])),
+        (FWFT, "FWFT", |_| sbi::fwft_get(0)),
+    ]
+};
+
+/// 对每个扩展比较 `probe_extension` 的结果和实际调用：探测为可用的扩展，
+/// 调用不能返回 `ERR_NOT_SUPPORTED`；探测为不可用的扩展，调用必须返回 `ERR_NOT_SUPPORTED`。
+/// 检验基础扩展的探测结果和固件实际的分发一致。
+pub(crate) fn test_probe_consistency(env: &Env) -> TestOutcome {
+    let mut ans = TestOutcome::Pass;
+    for (eid, name, call) in BENIGN_CALLS {
+        let available = sbi::probe_extension(eid);
+        let ret = call(env);
+        let dispatched = ret.error != sbi::ERR_NOT_SUPPORTED;
+        diag!("{name:<4} probe {available}, call error {:#x}", ret.error);
+        if available != dispatched {
+            diag!("{name} probe disagrees with dispatch");
+            ans = TestOutcome::Fail;
+        }
+    }
+    ans
+}
+
+/// 读 CSR。
+macro_rules! csr_read {
+    ($csr:literal) => {{
+        let value: usize;
+        unsafe { core::arch::asm!(concat!("csrr {}, ", $csr), out(reg) value) };
+        value
+    }};
+}
+
+/// 写 CSR。
+macro_rules! csr_write {
+    ($csr:literal, $value:expr) => {
+        unsafe { core::arch::asm!(concat!("csrw ", $csr, ", {}"), in(reg) $value) }
+    };
+}
+
+/// 把 `sscratch`、`stval`、`sepc` 设置为哨兵值，调用一次 `get_spec_version`，确认它们都没有变化，
+/// 检验 RustSBI 在 M 态处理调用时不破坏 S 态的状态。
+///
+/// 关闭 `sstatus.SIE` 进行，期间没有 S 态陷入会改写这些寄存器；结束后恢复原值。
+pub(crate) fn test_csr_preservation(_env: &Env) -> TestOutcome {
+    const NAMES: [&str; 3] = ["sscratch", "stval", "sepc"];
+    // sepc 的低位可能固定为 0，哨兵取 4 字节对齐的值
+    const SENTINELS: [usize; 3] = [0x5a5a_0a50, 0xa5a5_05a4, 0x5a5a_5a58];
+    let sie = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    let saved = [csr_read!("sscratch"), csr_read!("stval"), csr_read!("sepc")];
+    csr_write!("sscratch", SENTINELS[0]);
+    csr_write!("stval", SENTINELS[1]);
+    csr_write!("sepc", SENTINELS[2]);
+    sbi::get_spec_version();
+    let after = [csr_read!("sscratch"), csr_read!("stval"), csr_read!("sepc")];
+    csr_write!("sscratch", saved[0]);
+    csr_write!("stval", saved[1]);
+    csr_write!("sepc", saved[2]);
+    if sie {
+        unsafe { sstatus::set_sie() };
+    }
+
+    let mut preserved = true;
+    for ((name, sentinel), value) in NAMES.iter().zip(SENTINELS).zip(after) {
+        if value != sentinel {
+            diag!("{name} changed from {sentinel:#x} to {value:#x}");
+            preserved = false;
+        }
+    }
+    if preserved {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    sbi,
+    suite::{Env, TestOutcome},
+};
+
+/// 规范定义的固件特性。
+const FEATURES: [(u32, &str); 6] = [
+    (0, "MISALIGNED_EXC_DELEG"),
+    (1, "LANDING_PAD"),
+    (2, "SHADOW_STACK"),
+    (3, "DOUBLE_TRAP"),
+    (4, "PTE_AD_HW_UPDATING"),
+    (5, "POINTER_MASKING_PMLEN"),
+];
+/// 切换后能安全恢复、不影响本内核运行的特性。
+const MISALIGNED_EXC_DELEG: u32 = 0;
+
+/// 读出所有固件特性的值，再切换 `MISALIGNED_EXC_DELEG` 并恢复，确认读写都返回成功。
+pub(crate) fn test_fwft(_env: &Env) -> TestOutcome {
+    require_ext!(FWFT, "fwft");
+    for (feature, name) in FEATURES {
+        match sbi::fwft_get(feature).result() {
+            Ok(value) => diag!("{name} = {value}"),
+            Err(sbi::ERR_NOT_SUPPORTED) => diag!("{name} not supported"),
+            Err(e) => {
+                diag!("fwft_get({name}) failed with error {e:#x}");
+                return TestOutcome::Fail;
+            }
+        }
+    }
+
+    let Ok(value) = sbi::fwft_get(MISALIGNED_EXC_DELEG).result() else {
+        return TestOutcome::Pass;
+    };
+    match sbi::fwft_set(MISALIGNED_EXC_DELEG, value ^ 1, 0).result() {
+        Ok(_) => {}
+        Err(sbi::ERR_DENIED | sbi::ERR_NOT_SUPPORTED) => {
+            diag!("MISALIGNED_EXC_DELEG is not writable");
+            return TestOutcome::Pass;
+        }
+        Err(e) => {
+            diag!("fwft_set failed with error {e:#x}");
+            return TestOutcome::Fail;
+        }
+    }
+    let toggled = sbi::fwft_get(MISALIGNED_EXC_DELEG).result();
+    let restored = sbi::fwft_set(MISALIGNED_EXC_DELEG, value, 0).result();
+    diag!("MISALIGNED_EXC_DELEG toggled to {toggled:?}, restore {restored:?}");
+    if toggled == Ok(value ^ 1) && restored.is_ok() {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
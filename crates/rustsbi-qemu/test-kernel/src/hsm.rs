@@ -0,0 +1,45 @@
+use crate::{
+    smp,
+    suite::{Env, TestOutcome},
+    watchdog::Watchdog,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 从核进入任务的次数。
+static ENTERED: AtomicUsize = AtomicUsize::new(0);
+
+/// 反复启动、停止同一个从核，确认每次启动都进入了入口、每次停止都回到 STOPPED，
+/// 以发现 RustSBI 的 HSM 在多次循环后出现的资源泄漏或状态错乱。
+pub(crate) fn test_hsm_cycle(env: &Env) -> TestOutcome {
+    const CYCLES: usize = 20;
+    require_ext!(HSM, "hsm_cycle");
+    if env.board.smp < 2 {
+        diag!("SKIP hsm_cycle: single hart");
+        return TestOutcome::Skip;
+    }
+    // 硬件线程号可能不连续，取设备树中第一个从核
+    let Some(&hartid) = env.board.hart_ids().iter().find(|&&id| id != env.hartid) else {
+        diag!("SKIP hsm_cycle: no secondary hart in device tree");
+        return TestOutcome::Skip;
+    };
+    // 所有循环共用 5 秒
+    let watchdog = Watchdog::new(env.board.frequency, 5000);
+    ENTERED.store(0, Ordering::Relaxed);
+    for cycle in 0..CYCLES {
+        let result = smp::run_on_hart(env, hartid, enter, &watchdog);
+        let entered = ENTERED.load(Ordering::Acquire);
+        if result != Some(true) || entered != cycle + 1 {
+            diag!(
+                "hart {hartid} misbehaved in cycle {cycle}: result {result:?}, entered {entered} times"
+            );
+            return TestOutcome::Fail;
+        }
+    }
+    diag!("{CYCLES} start/stop cycles on hart {hartid}");
+    TestOutcome::Pass
+}
+
+fn enter(_env: &Env, _hartid: usize) -> bool {
+    ENTERED.fetch_add(1, Ordering::Release);
+    true
+}
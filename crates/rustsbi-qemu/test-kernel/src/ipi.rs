@@ -0,0 +1,242 @@
+use crate::{
+    irq::{IrqSource, IRQS},
+    sbi,
+    smp::{self, MAX_HARTS},
+    suite::{Env, TestOutcome},
+    trap,
+    watchdog::Watchdog,
+};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use riscv::register::{sip, sstatus, time};
+
+/// 已经准备好接收中断的从核数。
+static READY: AtomicUsize = AtomicUsize::new(0);
+/// 主核发送广播 IPI 的时刻。
+static START: AtomicU64 = AtomicU64::new(0);
+/// 各核收到中断时相对 `START` 的延迟，按 [`smp::slot`] 存放，`u64::MAX` 表示没有收到。
+static LATENCY: [AtomicU64; MAX_HARTS] = [const { AtomicU64::new(u64::MAX) }; MAX_HARTS];
+
+/// 直方图的桶数，第 `k` 个桶统计 `[2^k, 2^(k+1))` 个时钟周期的延迟，最后一个桶收纳更大的值。
+const BUCKETS: usize = 16;
+
+/// 主核用一次 `send_ipi` 向所有从核广播软件中断，各从核记录收到的时刻，
+/// 统计最小、最大延迟和分布，刻画 RustSBI 投递广播 IPI 是否均匀。
+pub(crate) fn test_ipi_broadcast_latency(env: &Env) -> TestOutcome {
+    require_ext!(SPI, "ipi_broadcast_latency");
+    require_ext!(HSM, "ipi_broadcast_latency");
+    if env.board.smp < 2 {
+        diag!("SKIP ipi_broadcast_latency: single hart");
+        return TestOutcome::Skip;
+    }
+    READY.store(0, Ordering::Relaxed);
+    for latency in &LATENCY {
+        latency.store(u64::MAX, Ordering::Relaxed);
+    }
+    if !smp::run_on_all_harts(env, broadcast).check(env.board) {
+        return TestOutcome::Fail;
+    }
+
+    let mut histogram = [0usize; BUCKETS];
+    let (mut min, mut max) = (u64::MAX, 0);
+    let ids = env.board.hart_ids().iter().enumerate();
+    for (slot, _) in ids.filter(|&(_, &id)| id != env.hartid) {
+        let latency = LATENCY[slot].load(Ordering::Acquire);
+        min = min.min(latency);
+        max = max.max(latency);
+        let bucket = latency.checked_ilog2().unwrap_or(0) as usize;
+        histogram[bucket.min(BUCKETS - 1)] += 1;
+    }
+    diag!(
+        "broadcast ipi latency min {min} max {max} spread {} ticks",
+        max - min
+    );
+    for (k, count) in histogram.iter().enumerate().filter(|(_, &n)| n > 0) {
+        diag!("  [2^{k:<2}, 2^{:<2}) ticks: {count}", k + 1);
+    }
+    TestOutcome::Pass
+}
+
+fn broadcast(env: &Env, hartid: usize) -> bool {
+    let ids = env.board.hart_ids();
+    // 最多等待 1 秒
+    let deadline = time::read64() + env.board.frequency;
+    if hartid == env.hartid {
+        while READY.load(Ordering::Acquire) < ids.len() - 1 {
+            if time::read64() > deadline {
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+        START.store(time::read64(), Ordering::Release);
+        return send_ipi_to(ids.iter().copied().filter(|&id| id != hartid));
+    }
+
+    // 清除残留的软件中断，再通知主核
+    unsafe { sip::clear_ssoft() };
+    READY.fetch_add(1, Ordering::Release);
+    while !sip::read().ssoft() {
+        if time::read64() > deadline {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    let now = time::read64();
+    unsafe { sip::clear_ssoft() };
+    let latency = now.saturating_sub(START.load(Ordering::Acquire));
+    let Some(slot) = smp::slot(env.board, hartid) else {
+        return false;
+    };
+    LATENCY[slot].store(latency, Ordering::Release);
+    true
+}
+
+/// 向 `ids` 中的硬件线程发送软件中断，全部调用成功时返回 `true`。
+///
+/// 硬件线程号可能不连续，也可能超出一个掩码的宽度：按 `hart_mask_base` 分组，
+/// 每组调用一次 `send_ipi`，所有硬件线程号都小于 `usize::BITS` 时只调用一次。
+fn send_ipi_to(ids: impl Iterator<Item = usize> + Clone) -> bool {
+    let bits = usize::BITS as usize;
+    let mut ok = true;
+    for (i, id) in ids.clone().enumerate() {
+        let base = id - id % bits;
+        // 同一组已经由更早的硬件线程发送过
+        if ids
+            .clone()
+            .take(i)
+            .any(|other| other - other % bits == base)
+        {
+            continue;
+        }
+        let mask = ids
+            .clone()
+            .filter(|&other| other - other % bits == base)
+            .fold(0, |mask, other| mask | 1 << (other - base));
+        ok &= sbi::send_ipi(mask, base).result().is_ok();
+    }
+    ok
+}
+
+/// 用 `send_ipi` 只选中本核，向自己发送软件中断，确认本核进入处理函数，并报告延迟。
+///
+/// 不需要从核，单核也能运行。
+pub(crate) fn test_ipi_self(env: &Env) -> TestOutcome {
+    require_ext!(SPI, "ipi_self");
+    let before = trap::SSOFT.load(Ordering::Acquire);
+    unsafe { sip::clear_ssoft() };
+    IRQS.enable(env.hartid, IrqSource::Software);
+    unsafe { sstatus::set_sie() };
+    let start = time::read64();
+    let ret = sbi::send_ipi(1, env.hartid);
+    let watchdog = Watchdog::new(env.board.frequency, 100);
+    while trap::SSOFT.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    let latency = time::read64() - start;
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Software);
+    if let Err(e) = ret.result() {
+        diag!("send_ipi to self failed with error {e}");
+        return TestOutcome::Fail;
+    }
+    if trap::SSOFT.load(Ordering::Acquire) == before {
+        diag!("self ipi not received within 100 ms");
+        return TestOutcome::Fail;
+    }
+    diag!("self ipi latency {latency} ticks");
+    TestOutcome::Pass
+}
+
+/// 分四种情况检查软件中断的撤销，每种重复多次：
+///
+/// 1. 陷入之后、处理函数读 SSIP 之前撤销：处理函数每次都走虚假中断的分支；
+/// 2. 用 `csrs sip` 置位 SSIP 后在打开中断之前清除：不应进入处理函数；
+/// 3. 直接写 CLINT 置位再清除本核的 MSIP，和固件把 MSIP 转发成 SSIP 竞争：每轮最多处理一次，
+///    不会有虚假中断，结束后 MSIP 和 SSIP 都不挂起；没有 CLINT 时跳过这种情况；
+/// 4. 保持 SSIP 置位，确认同一条路径上的中断确实会被处理。
+pub(crate) fn test_spurious_irq(env: &Env) -> TestOutcome {
+    const ROUNDS: usize = 100;
+    let counts = || {
+        (
+            trap::SSOFT.load(Ordering::Relaxed),
+            trap::SPURIOUS.load(Ordering::Relaxed),
+        )
+    };
+    let since = |(handled, spurious): (usize, usize)| {
+        let (now_handled, now_spurious) = counts();
+        (now_handled - handled, now_spurious - spurious)
+    };
+    let window = || unsafe {
+        sstatus::set_sie();
+        core::hint::spin_loop();
+        sstatus::clear_sie();
+    };
+    IRQS.enable(env.hartid, IrqSource::Software);
+
+    let start = counts();
+    for _ in 0..ROUNDS {
+        trap::RETRACT_SSOFT.store(true, Ordering::Relaxed);
+        unsafe { sip::set_ssoft() };
+        window();
+    }
+    trap::RETRACT_SSOFT.store(false, Ordering::Relaxed);
+    let in_handler = since(start);
+
+    let start = counts();
+    for _ in 0..ROUNDS {
+        unsafe {
+            sip::set_ssoft();
+            sip::clear_ssoft();
+        }
+        window();
+    }
+    let before_sie = since(start);
+
+    let start = counts();
+    let clint_race = env.clint.map(|clint| {
+        unsafe { sstatus::set_sie() };
+        for _ in 0..ROUNDS {
+            clint.set_msip(env.hartid);
+            clint.clear_msip(env.hartid);
+        }
+        unsafe { sstatus::clear_sie() };
+        let left = clint.read_msip(env.hartid) || sip::read().ssoft();
+        (since(start), left)
+    });
+
+    let start = counts();
+    unsafe { sip::set_ssoft() };
+    window();
+    let kept = since(start);
+    IRQS.disable(env.hartid, IrqSource::Software);
+    let pending = sip::read().ssoft();
+    unsafe { sip::clear_ssoft() };
+
+    diag!(
+        "{ROUNDS} retracted in handler: {} handled, {} spurious",
+        in_handler.0,
+        in_handler.1
+    );
+    diag!(
+        "{ROUNDS} retracted before SIE: {} handled, {} spurious",
+        before_sie.0,
+        before_sie.1
+    );
+    let clint_ok = match clint_race {
+        Some(((handled, spurious), left)) => {
+            diag!(
+                "{ROUNDS} CLINT msip races: {handled} handled, {spurious} spurious, left pending {left}"
+            );
+            handled <= ROUNDS && spurious == 0 && !left
+        }
+        None => {
+            diag!("CLINT msip race skipped: no CLINT");
+            true
+        }
+    };
+    diag!("kept: {} handled, {} spurious", kept.0, kept.1);
+    if in_handler == (0, ROUNDS) && before_sie == (0, 0) && clint_ok && kept == (1, 0) && !pending {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
@@ -0,0 +1,111 @@
+//! 中断使能记录。
+
+use crate::smp::MAX_HARTS;
+use crate::suite::{Env, TestOutcome};
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use riscv::register::sie;
+
+/// S 态中断源，值是它在 `sie`/`sip` 中的位。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(usize)]
+pub(crate) enum IrqSource {
+    Software = 1,
+    Timer = 5,
+    CounterOverflow = 13,
+}
+
+impl IrqSource {
+    #[inline]
+    const fn bit(self) -> usize {
+        1 << self as usize
+    }
+}
+
+/// 记录测试在各硬件线程上打开了哪些中断源，测试结束后能把 `sie` 恢复原样，
+/// 避免打开的中断泄漏到后面的测试。
+///
+/// 每个核第一次 `enable` 时保存当时的 `sie`，`restore` 把它写回，测试直接用 `csrs` 打开的位也会被撤销。
+/// `sie` 是每个核自己的 CSR，所以 `enable`、`disable` 和 `restore` 只能由 `hart` 本身调用。
+pub(crate) struct HartIrqState<const N: usize>([HartIrq; N]);
+
+/// 一个核的记录。
+struct HartIrq {
+    /// 记录下来的中断源。
+    enabled: AtomicUsize,
+    /// 第一次 `enable` 之前的 `sie`。
+    saved: AtomicUsize,
+    /// `saved` 是否有效。
+    snapshot: AtomicBool,
+}
+
+impl<const N: usize> HartIrqState<N> {
+    pub const fn new() -> Self {
+        Self(
+            [const {
+                HartIrq {
+                    enabled: AtomicUsize::new(0),
+                    saved: AtomicUsize::new(0),
+                    snapshot: AtomicBool::new(false),
+                }
+            }; N],
+        )
+    }
+
+    /// 打开本核的中断源 `src` 并记录下来，本核第一次打开时先保存 `sie`。
+    pub fn enable(&self, hart: usize, src: IrqSource) {
+        let state = &self.0[hart];
+        if !state.snapshot.swap(true, Ordering::Relaxed) {
+            state.saved.store(sie::read().bits(), Ordering::Relaxed);
+        }
+        state.enabled.fetch_or(src.bit(), Ordering::Relaxed);
+        unsafe { asm!("csrs sie, {}", in(reg) src.bit()) };
+    }
+
+    /// 关闭本核的中断源 `src`，不再记录。
+    pub fn disable(&self, hart: usize, src: IrqSource) {
+        self.0[hart]
+            .enabled
+            .fetch_and(!src.bit(), Ordering::Relaxed);
+        unsafe { asm!("csrc sie, {}", in(reg) src.bit()) };
+    }
+
+    /// 把本核的 `sie` 恢复到第一次 `enable` 之前的值；没有保存过时关闭记录的中断源。
+    pub fn restore(&self, hart: usize) {
+        let state = &self.0[hart];
+        let enabled = state.enabled.swap(0, Ordering::Relaxed);
+        if state.snapshot.swap(false, Ordering::Relaxed) {
+            let saved = state.saved.load(Ordering::Relaxed);
+            unsafe { asm!("csrw sie, {}", in(reg) saved) };
+        } else {
+            unsafe { asm!("csrc sie, {}", in(reg) enabled) };
+        }
+    }
+}
+
+/// 测试共用的中断使能记录。
+pub(crate) static IRQS: HartIrqState<MAX_HARTS> = HartIrqState::new();
+
+/// 打开两个中断源，再直接用 `csrs` 打开第三个，`restore` 后确认 `sie` 回到开始时的值。
+///
+/// 期间 `sstatus.SIE` 保持关闭，打开的中断不会被响应。
+pub(crate) fn test_irq_restore(env: &Env) -> TestOutcome {
+    // 先撤销之前的记录，从干净的状态开始
+    IRQS.restore(env.hartid);
+    let before = sie::read().bits();
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    IRQS.enable(env.hartid, IrqSource::Software);
+    unsafe { asm!("csrs sie, {}", in(reg) IrqSource::CounterOverflow.bit()) };
+    let during = sie::read().bits();
+    IRQS.restore(env.hartid);
+    let after = sie::read().bits();
+    diag!("sie before {before:#x}, during {during:#x}, after {after:#x}");
+    let raised = IrqSource::Timer.bit() | IrqSource::Software.bit();
+    if during & raised == raised && after == before {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
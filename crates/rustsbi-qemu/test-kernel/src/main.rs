@@ -6,30 +6,39 @@
 #[macro_use]
 extern crate rcore_console;
 
-use core::{ptr::null, arch::{asm, naked_asm}};
-
-// 简化的SBI接口
-mod sbi {
-    use core::arch::asm;
+#[macro_use]
+mod suite;
 
-    pub const SHUTDOWN: u32 = 0;
-    pub const NO_REASON: u32 = 0;
-    pub const SYSTEM_FAILURE: u32 = 1;
+mod clint;
+mod console;
+mod ecall;
+mod fwft;
+mod hsm;
+mod ipi;
+mod irq;
+mod pmu;
+mod reset;
+mod rfence;
+mod sbi;
+mod smp;
+mod soak;
+mod timer;
+mod trap;
+mod watchdog;
 
-    pub fn system_reset(reset_type: u32, reset_reason: u32) {
-        unsafe {
-            asm!(
-                "ecall",
-                inlateout("a0") reset_reason => _,
-                inlateout("a1") reset_type => _,
-                in("a7") 0x8000_0008u32 as i32, // SBI_EXT_RESET_SYSTEM
-                options(nomem, nostack),
-            );
-        }
-    }
-}
+use aclint::TimebaseFrequency;
+use core::{ops::Range, ptr::null, arch::{asm, naked_asm}};
+use spin::Once;
+use smp::MAX_HARTS;
+use suite::Env;
 use uart16550::Uart16550;
 
+/// 每个硬件线程设置 16KiB 栈空间。
+const STACK_SIZE: usize = 16384;
+
+#[link_section = ".bss.uninit"]
+static mut STACK: [u8; STACK_SIZE * MAX_HARTS] = [0u8; STACK_SIZE * MAX_HARTS];
+
 /// 内核入口。
 ///
 /// # Safety
@@ -39,20 +48,60 @@ use uart16550::Uart16550;
 #[no_mangle]
 #[link_section = ".text.entry"]
 unsafe extern "C" fn _start(hartid: usize, device_tree_paddr: usize) -> ! {
-    const STACK_SIZE: usize = 16384; // 16 KiB
-
-    #[link_section = ".bss.uninit"]
-    static mut STACK: [u8; STACK_SIZE] = [0u8; STACK_SIZE];
-
     naked_asm!(
-        "la sp, {stack} + {stack_size}",
+        // 硬件线程号超出栈的数量时停在这里，不越过 STACK 的末尾
+        "   li   t0, {max_harts}
+            bgeu a0, t0, 2f
+        ",
+        // sp = STACK + (hartid + 1) * STACK_SIZE
+        "   addi t0, a0, 1
+            li   t1, {stack_size}
+            mul  t0, t0, t1
+            la   sp, {stack}
+            add  sp, sp, t0
+            mv   tp, a0
+        ",
         "j  {main}",
+        "2: wfi
+            j  2b
+        ",
+        max_harts  = const MAX_HARTS,
         stack_size = const STACK_SIZE,
         stack      =   sym STACK,
         main       =   sym rust_main,
     )
 }
 
+/// 从核入口，由 [`smp::run_on_all_harts`] 通过 HSM 启动。
+///
+/// # Safety
+///
+/// 裸函数。
+#[unsafe(naked)]
+unsafe extern "C" fn secondary_start(hartid: usize, opaque: usize) -> ! {
+    naked_asm!(
+        // 同 `_start`，硬件线程号超出栈的数量时停在这里
+        "   li   t0, {max_harts}
+            bgeu a0, t0, 2f
+        ",
+        "   addi t0, a0, 1
+            li   t1, {stack_size}
+            mul  t0, t0, t1
+            la   sp, {stack}
+            add  sp, sp, t0
+            mv   tp, a0
+        ",
+        "j  {main}",
+        "2: wfi
+            j  2b
+        ",
+        max_harts  = const MAX_HARTS,
+        stack_size = const STACK_SIZE,
+        stack      =   sym STACK,
+        main       =   sym smp::secondary_main,
+    )
+}
+
 extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
     extern "C" {
         static mut sbss: u64;
@@ -66,16 +115,26 @@ extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
             ptr = ptr.offset(1);
         }
     }
+    static BOARD: Once<BoardInfo> = Once::new();
+    let board = BOARD.call_once(|| BoardInfo::parse(hartid, dtb_pa));
     let BoardInfo {
         smp,
         frequency,
         uart,
-    } = BoardInfo::parse(dtb_pa);
+        ref isa,
+        clint,
+        ..
+    } = *board;
     unsafe { *(&raw mut UART as *mut Uart16550Map) = Uart16550Map(uart as _); };
     rcore_console::init_console(&Console);
     rcore_console::set_log_level(option_env!("LOG"));
-    println!(
-        r"
+    // 按字长读取 time 时计数器回绕的时间，RV32 上只有几分钟
+    let rollover = TimebaseFrequency(frequency).rollover_seconds_for(usize::BITS);
+    let tick = TimebaseFrequency(frequency).min_representable_nanos();
+    // TAP 输出时不打印横幅和固件信息，`1..N` 之前只能有注释行
+    if !cfg!(feature = "tap-output") {
+        println!(
+            r"
  _____         _     _  __                    _
 |_   _|__  ___| |_  | |/ /___ _ __ _ __   ___| |
   | |/ _ \/ __| __| | ' // _ \ '__| '_ \ / _ \ |
@@ -85,12 +144,39 @@ extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
 | boot hart id          | {hartid:20} |
 | smp                   | {smp:20} |
 | timebase frequency    | {frequency:17} Hz |
+| time rollover         | {rollover:18} s |
+| tick granularity      | {tick:17} ns |
+| boot hart isa         | {isa:20} |
 | dtb physical address  | {dtb_pa:#20x} |
+| clint base address    | {clint:#20x} |
 ------------------------------------------------"
-    );
-    // 简单的测试，直接通过
-    println!("[test-kernel] SBI test PASSED");
-    sbi::system_reset(sbi::SHUTDOWN, sbi::NO_REASON);
+        );
+        sbi::print_firmware_info();
+    }
+    check_image_in_memory(board);
+    check_stack_alignment();
+    trap::install();
+    let env = Env {
+        hartid,
+        board,
+        clint: clint::verified_clint(board),
+    };
+    if let Some(clint) = env.clint {
+        clint::warn_armed_timer(clint, hartid);
+    }
+    let passed = if cfg!(feature = "soak") {
+        soak::run(&env)
+    } else {
+        suite::run_all(&env)
+    };
+    // 最后完整走一遍时钟中断，确认固件的时钟实现可用
+    if passed && timer::round_trip(&env) {
+        note!("SBI test PASSED");
+        sbi::system_reset(sbi::SHUTDOWN, sbi::NO_REASON);
+    } else {
+        note!("SBI test FAILED");
+        sbi::system_reset(sbi::SHUTDOWN, sbi::SYSTEM_FAILURE);
+    }
     unreachable!()
 }
 
@@ -99,28 +185,61 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     let (hart_id, pc): (usize, usize);
     unsafe { asm!("mv    {}, tp", out(reg) hart_id) };
     unsafe { asm!("auipc {},  0", out(reg) pc) };
-    println!("[test-kernel-panic] hart {hart_id} {info}");
-    println!("[test-kernel-panic] pc = {pc:#x}");
-    println!("[test-kernel-panic] SBI test FAILED due to panic");
+    if cfg!(feature = "tap-output") {
+        println!("Bail out! hart {hart_id} panicked at pc {pc:#x}: {info}");
+    } else {
+        println!("[test-kernel-panic] hart {hart_id} {info}");
+        println!("[test-kernel-panic] pc = {pc:#x}");
+        println!("[test-kernel-panic] SBI test FAILED due to panic");
+    }
     sbi::system_reset(sbi::SHUTDOWN, sbi::SYSTEM_FAILURE);
     loop {}
 }
 
 struct BoardInfo {
     smp: usize,
+    /// 设备树中各 cpu 节点的硬件线程号，只记录前 [`MAX_HARTS`] 个。
+    harts: [usize; MAX_HARTS],
     frequency: u64,
+    /// 启动核的 `clock-frequency`，设备树没有给出时为 0。
+    cpu_frequency: u64,
     uart: usize,
+    isa: StringInline<128>,
+    clint: usize,
+    mem: Range<usize>,
 }
 
 impl BoardInfo {
-    fn parse(dtb_pa: usize) -> Self {
+    /// 把 MTIME 的计数换算成 CPU 周期数，设备树没有给出 CPU 频率时返回 `None`。
+    fn mtime_ticks_to_cpu_cycles(&self, ticks: u64) -> Option<u64> {
+        timer::convert_ticks(ticks, self.frequency, self.cpu_frequency)
+    }
+
+    /// 把 CPU 周期数换算成 MTIME 的计数，设备树没有给出 CPU 频率时返回 `None`。
+    fn cpu_cycles_to_mtime_ticks(&self, cycles: u64) -> Option<u64> {
+        timer::convert_ticks(cycles, self.cpu_frequency, self.frequency)
+    }
+
+    /// 设备树给出的硬件线程号，可能不连续。
+    fn hart_ids(&self) -> &[usize] {
+        &self.harts[..self.smp.min(MAX_HARTS)]
+    }
+
+    fn parse(hartid: usize, dtb_pa: usize) -> Self {
         use dtb_walker::{Dtb, DtbObj, HeaderError as E, Property, Str, WalkOperation::*};
 
         let mut ans = Self {
             smp: 0,
+            harts: [0; MAX_HARTS],
             frequency: 0,
+            cpu_frequency: 0,
             uart: 0,
+            isa: StringInline::new(),
+            clint: 0,
+            mem: 0..0,
         };
+        // 正在访问的节点。属性总在子节点之前，所以属性都属于最近进入的节点
+        let mut node = SocNode::default();
         unsafe {
             Dtb::from_raw_parts_filtered(dtb_pa as _, |e| {
                 matches!(e, E::Misaligned(4) | E::LastCompVersion(_))
@@ -129,14 +248,32 @@ impl BoardInfo {
         .unwrap()
         .walk(|ctx, obj| match obj {
             DtbObj::SubNode { name } => {
-                if ctx.is_root() && (name == Str::from("cpus") || name == Str::from("soc")) {
+                node = SocNode::default();
+                if ctx.is_root()
+                    && (name == Str::from("cpus")
+                        || name == Str::from("soc")
+                        || name.starts_with("memory"))
+                {
                     StepInto
                 } else if ctx.name() == Str::from("cpus") && name.starts_with("cpu@") {
+                    let id = unit_address(name);
+                    if let (Some(id), Some(slot)) = (id, ans.harts.get_mut(ans.smp)) {
+                        *slot = id;
+                    }
                     ans.smp += 1;
-                    StepOver
-                } else if ctx.name() == Str::from("soc")
-                    && (name.starts_with("uart") || name.starts_with("serial"))
-                {
+                    // 只有启动核的节点需要进入，以读取 riscv,isa
+                    if id == Some(hartid) {
+                        StepInto
+                    } else {
+                        StepOver
+                    }
+                } else if ctx.name() == Str::from("soc") {
+                    // compatible 可能排在 reg 之后，每个子节点都要进入才能判断是不是 CLINT
+                    node = SocNode {
+                        in_soc: true,
+                        clint: name.starts_with("clint"),
+                        reg: None,
+                    };
                     StepInto
                 } else {
                     StepOver
@@ -145,16 +282,30 @@ impl BoardInfo {
             DtbObj::Property(Property::Reg(mut reg)) => {
                 if ctx.name().starts_with("uart") || ctx.name().starts_with("serial") {
                     ans.uart = reg.next().unwrap().start;
+                    StepOut
+                } else if ctx.name().starts_with("memory") {
+                    ans.mem = reg.next().unwrap();
+                    StepOut
+                } else if node.in_soc {
+                    node.reg = Some(reg.next().map_or(0, |range| range.start));
+                    node.found(&mut ans.clint)
+                } else {
+                    StepOver
                 }
-                StepOut
+            }
+            DtbObj::Property(Property::Compatible(mut compatible)) if node.in_soc => {
+                node.clint |= compatible.any(|c| {
+                    c == Str::from("riscv,clint0") || c == Str::from("sifive,clint0")
+                });
+                node.found(&mut ans.clint)
             }
             DtbObj::Property(Property::General { name, value }) => {
                 if ctx.name() == Str::from("cpus") && name == Str::from("timebase-frequency") {
-                    ans.frequency = match *value {
-                        [a, b, c, d] => u32::from_be_bytes([a, b, c, d]) as _,
-                        [a, b, c, d, e, f, g, h] => u64::from_be_bytes([a, b, c, d, e, f, g, h]),
-                        _ => unreachable!(),
-                    };
+                    ans.frequency = be_number(value);
+                } else if ctx.name().starts_with("cpu@") && name == Str::from("clock-frequency") {
+                    ans.cpu_frequency = be_number(value);
+                } else if ctx.name().starts_with("cpu@") && name == Str::from("riscv,isa") {
+                    ans.isa.copy_from(value);
                 }
                 StepOver
             }
@@ -164,6 +315,132 @@ impl BoardInfo {
     }
 }
 
+/// 设备树解析时 soc 下正在访问的子节点。
+#[derive(Default)]
+struct SocNode {
+    in_soc: bool,
+    /// 节点名或 compatible 表明是 CLINT。
+    clint: bool,
+    /// 第一段 reg 的起始地址，没有 reg 时为 0。
+    reg: Option<usize>,
+}
+
+impl SocNode {
+    /// 确认是 CLINT 并读到 reg 后记录基地址并离开节点，只记录第一个 CLINT。
+    fn found(&self, clint: &mut usize) -> dtb_walker::WalkOperation {
+        match self.reg {
+            Some(reg) if self.clint => {
+                if *clint == 0 {
+                    *clint = reg;
+                }
+                dtb_walker::WalkOperation::StepOut
+            }
+            _ => dtb_walker::WalkOperation::StepOver,
+        }
+    }
+}
+
+/// 检查 `sp` 按 RISC-V 调用约定 16 字节对齐，否则说明入口设置的栈顶有误。
+fn check_stack_alignment() {
+    let sp: usize;
+    unsafe { asm!("mv {}, sp", out(reg) sp) };
+    if !sp.is_multiple_of(16) {
+        let align = 1usize << sp.trailing_zeros();
+        note!("WARNING: sp {sp:#x} is only {align}-byte aligned");
+    }
+}
+
+/// 检查内核的启动栈和 bss 段都位于设备树描述的内存中，否则说明链接地址和设备树不符。
+///
+/// 设备树没有 `memory` 节点时无从比较，跳过检查。
+fn check_image_in_memory(board: &BoardInfo) {
+    extern "C" {
+        static sstack: u8;
+        static estack: u8;
+        static sbss: u8;
+        static ebss: u8;
+    }
+    let stack = &raw const sstack as usize..&raw const estack as usize;
+    let bss = &raw const sbss as usize..&raw const ebss as usize;
+    let mem = &board.mem;
+    if mem.is_empty() {
+        note!("memory not found in device tree, image placement not checked");
+        return;
+    }
+    for (name, range) in [("stack", stack), ("bss", bss)] {
+        if range.start < mem.start || range.end > mem.end {
+            note!("WARNING: {name} {range:#x?} is outside of memory {mem:#x?}");
+        }
+    }
+}
+
+/// 解析 1 或 2 个 cell 的设备树数值属性。
+fn be_number(value: &[u8]) -> u64 {
+    match *value {
+        [a, b, c, d] => u32::from_be_bytes([a, b, c, d]) as _,
+        [a, b, c, d, e, f, g, h] => u64::from_be_bytes([a, b, c, d, e, f, g, h]),
+        _ => unreachable!(),
+    }
+}
+
+/// 从节点名 `name@addr` 中解析单元地址。
+fn unit_address(name: dtb_walker::Str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    let at = bytes.iter().position(|&b| b == b'@')?;
+    let hex = core::str::from_utf8(&bytes[at + 1..]).ok()?;
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// 在栈上存储有限长度字符串。第一项是拷贝来源的长度，可能超过 `N`。
+struct StringInline<const N: usize>(usize, [u8; N]);
+
+impl<const N: usize> StringInline<N> {
+    #[inline]
+    const fn new() -> Self {
+        Self(0, [0u8; N])
+    }
+
+    /// 从设备树字符串属性拷贝，去掉结尾的 `\0`，超长部分截断。
+    fn copy_from(&mut self, value: &[u8]) {
+        let value = match value.iter().position(|&b| b == 0) {
+            Some(end) => &value[..end],
+            None => value,
+        };
+        self.0 = value.len();
+        let len = self.0.min(N);
+        self.1[..len].copy_from_slice(&value[..len]);
+    }
+
+    /// 拷贝时是否截断了超长部分。
+    fn truncated(&self) -> bool {
+        self.0 > N
+    }
+
+    /// 已存储的字符串，截断处不是字符边界时只保留有效部分。
+    fn as_str(&self) -> &str {
+        let bytes = &self.1[..self.0.min(N)];
+        match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => unsafe { core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+        }
+    }
+}
+
+impl<const N: usize> core::fmt::Display for StringInline<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.as_str() {
+            "" => f.pad("unknown"),
+            s => f.pad(s),
+        }
+    }
+}
+
+/// 内核使用的串口。
+fn uart() -> &'static Uart16550<u8> {
+    let uart = &raw const UART;
+    unsafe { (*uart).get() }
+}
+
 struct Console;
 static mut UART: Uart16550Map = Uart16550Map(null());
 
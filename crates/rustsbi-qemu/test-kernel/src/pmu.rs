@@ -0,0 +1,130 @@
+use crate::{
+    irq::{IrqSource, IRQS},
+    sbi,
+    suite::{Env, TestOutcome},
+    watchdog::Watchdog,
+};
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use riscv::register::sstatus;
+
+/// 计数器溢出中断（LCOFI）在 `sie`/`sip` 中的位。
+pub(crate) const LCOFI: usize = 13;
+
+/// 事件：硬件周期数。
+const HW_CPU_CYCLES: usize = 1;
+/// 配置时清零计数器。
+const CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+/// 启动时设置初值。
+const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+/// 溢出前要计的事件数。
+const PERIOD: u64 = 10_000;
+
+/// 正在测试的计数器，`usize::MAX` 表示没有。
+static COUNTER: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// 计数器的 CSR 编号。
+static COUNTER_CSR: AtomicUsize = AtomicUsize::new(0);
+/// 处理过的溢出中断数。
+static OVERFLOWS: AtomicUsize = AtomicUsize::new(0);
+/// 处理函数读到的计数器值。
+static OBSERVED: AtomicUsize = AtomicUsize::new(0);
+
+/// 配置一个统计周期数的硬件计数器，使它在 [`PERIOD`] 个周期后溢出，打开溢出中断，
+/// 确认中断到达 S 态并且处理函数能读出计数器。需要 PMU 扩展和 Sscofpmf。
+pub(crate) fn test_pmu_overflow(env: &Env) -> TestOutcome {
+    require_ext!(PMU, "pmu_overflow");
+    if !env.board.isa.as_str().contains("sscofpmf") {
+        diag!("SKIP pmu_overflow: Sscofpmf unavailable");
+        return TestOutcome::Skip;
+    }
+    let Ok(num) = sbi::pmu_num_counters().result() else {
+        return TestOutcome::Fail;
+    };
+    // 只有 hpmcounter3 及以后的计数器能产生溢出中断
+    if num <= 3 {
+        diag!("SKIP pmu_overflow: no programmable counter");
+        return TestOutcome::Skip;
+    }
+    let mask = (1 << (num - 3)) - 1;
+    let counter =
+        match sbi::pmu_counter_config_matching(3, mask, CFG_FLAG_CLEAR_VALUE, HW_CPU_CYCLES, 0)
+            .result()
+        {
+            Ok(counter) => counter,
+            Err(e) => {
+                diag!("SKIP pmu_overflow: no counter for cycles (error {e})");
+                return TestOutcome::Skip;
+            }
+        };
+    let Ok(info) = sbi::pmu_counter_get_info(counter).result() else {
+        return TestOutcome::Fail;
+    };
+    let (csr, width) = (info & 0xfff, ((info >> 12) & 0x3f) as u32 + 1);
+    let initial = (u64::MAX >> (64 - width)) - PERIOD + 1;
+
+    OVERFLOWS.store(0, Ordering::Relaxed);
+    COUNTER_CSR.store(csr, Ordering::Relaxed);
+    COUNTER.store(counter, Ordering::Release);
+    IRQS.enable(env.hartid, IrqSource::CounterOverflow);
+    unsafe { sstatus::set_sie() };
+    let started = sbi::pmu_counter_start(counter, 1, START_FLAG_SET_INIT_VALUE, initial);
+    let watchdog = Watchdog::new(env.board.frequency, 1000);
+    while started.error == 0 && OVERFLOWS.load(Ordering::Acquire) == 0 && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::CounterOverflow);
+    sbi::pmu_counter_stop(counter, 1, 0);
+    COUNTER.store(usize::MAX, Ordering::Relaxed);
+
+    if let Err(e) = started.result() {
+        diag!("counter_start failed with error {e}");
+        return TestOutcome::Fail;
+    }
+    let overflows = OVERFLOWS.load(Ordering::Acquire);
+    diag!(
+        "counter {counter} (csr {csr:#x}, {width} bits): {overflows} overflows, read {:#x} in handler",
+        OBSERVED.load(Ordering::Relaxed)
+    );
+    if overflows > 0 {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 处理计数器溢出中断：读出计数器，停止它并清除 LCOFIP。
+pub(crate) fn handle_overflow() {
+    let counter = COUNTER.load(Ordering::Acquire);
+    if counter != usize::MAX {
+        OBSERVED.store(
+            read_hpmcounter(COUNTER_CSR.load(Ordering::Relaxed)),
+            Ordering::Relaxed,
+        );
+        sbi::pmu_counter_stop(counter, 1, 0);
+    }
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << LCOFI) };
+    OVERFLOWS.fetch_add(1, Ordering::Release);
+}
+
+/// 读 `hpmcounter3` 到 `hpmcounter31`，CSR 编号只能写在指令里，所以逐个展开。
+fn read_hpmcounter(csr: usize) -> usize {
+    macro_rules! read {
+        ($($csr:literal)*) => {
+            match csr {
+                $($csr => {
+                    let val: usize;
+                    unsafe { asm!(concat!("csrr {}, ", $csr), out(reg) val) };
+                    val
+                })*
+                _ => 0,
+            }
+        };
+    }
+    read!(
+        0xc03 0xc04 0xc05 0xc06 0xc07 0xc08 0xc09 0xc0a 0xc0b 0xc0c 0xc0d 0xc0e 0xc0f 0xc10 0xc11
+        0xc12 0xc13 0xc14 0xc15 0xc16 0xc17 0xc18 0xc19 0xc1a 0xc1b 0xc1c 0xc1d 0xc1e 0xc1f
+    )
+}
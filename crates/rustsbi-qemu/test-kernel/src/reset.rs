@@ -0,0 +1,123 @@
+use crate::{
+    sbi,
+    suite::{Env, TestOutcome},
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 跨越重启保存的进度。
+///
+/// 放在单独的 `.sentinel` 段，位于 bss 之后，既不清零也不属于内核镜像，重启后 qemu 只重新加载镜像，
+/// 这里的内容保留。栈向下生长，溢出也写不到这里；校验和不对时说明内容被破坏。
+#[repr(C)]
+struct Sentinel {
+    magic: u64,
+    boot: u64,
+    /// 重启前请求的复位类型和原因。
+    reset_type: u32,
+    reset_reason: u32,
+    checksum: u64,
+}
+
+impl Sentinel {
+    fn sum(&self) -> u64 {
+        let request = (self.reset_type as u64) << 32 | self.reset_reason as u64;
+        (self.magic ^ self.boot.rotate_left(21) ^ request.rotate_left(42))
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn valid(&self) -> bool {
+        self.magic == MAGIC && self.checksum == self.sum()
+    }
+
+    /// 记录下一次启动的序号和这次请求的复位，更新校验和。
+    fn record(&mut self, boot: u64, reset_type: u32, reset_reason: u32) {
+        self.magic = MAGIC;
+        self.boot = boot;
+        self.reset_type = reset_type;
+        self.reset_reason = reset_reason;
+        self.checksum = self.sum();
+    }
+}
+
+const MAGIC: u64 = 0x7265_7365_745f_7473;
+
+#[link_section = ".sentinel"]
+static mut SENTINEL: Sentinel = Sentinel {
+    magic: 0,
+    boot: 0,
+    reset_type: 0,
+    reset_reason: 0,
+    checksum: 0,
+};
+
+/// 本次启动已经运行过复位测试。在 `.bss` 中，每次启动清零。
+static RAN: AtomicBool = AtomicBool::new(false);
+
+/// 按顺序以不同的类型和原因复位：每次重启前把请求的类型和原因记录在哨兵中，
+/// 重启后确认哨兵完好、记录的正是上一步请求的复位，最后报告完成的步数。
+///
+/// 需要打开 `reboot` 特性。每次启动只运行一次：浸泡测试的后续轮次跳过它，
+/// 否则完成后清除的哨兵会让下一轮从头重启，永远停不下来。
+pub(crate) fn test_reset_reasons(_env: &Env) -> TestOutcome {
+    if !cfg!(feature = "reboot") {
+        return TestOutcome::Skip;
+    }
+    require_ext!(SRST, "reset_reasons");
+    if RAN.swap(true, Ordering::Relaxed) {
+        diag!("SKIP reset_reasons: already ran this boot");
+        return TestOutcome::Skip;
+    }
+    const STEPS: [(u32, u32, &str); 2] = [
+        (sbi::WARM_REBOOT, sbi::NO_REASON, "warm reboot, no reason"),
+        (
+            sbi::COLD_REBOOT,
+            sbi::SYSTEM_FAILURE,
+            "cold reboot, system failure",
+        ),
+    ];
+    let sentinel = &raw mut SENTINEL;
+    let sentinel = unsafe { &mut *sentinel };
+    let boot = if sentinel.valid() {
+        sentinel.boot as usize
+    } else if sentinel.magic == MAGIC {
+        sentinel.magic = 0;
+        diag!(
+            "sentinel corrupted: checksum {:#x}, expected {:#x}",
+            sentinel.checksum,
+            sentinel.sum()
+        );
+        return TestOutcome::Fail;
+    } else {
+        0
+    };
+    if let Some(&(reset_type, reset_reason, what)) = boot.checked_sub(1).and_then(|i| STEPS.get(i))
+    {
+        let requested = (sentinel.reset_type, sentinel.reset_reason);
+        if requested != (reset_type, reset_reason) {
+            sentinel.magic = 0;
+            diag!(
+                "boot {boot}: recorded reset type {:#x} reason {:#x}, expected {what}",
+                requested.0,
+                requested.1
+            );
+            return TestOutcome::Fail;
+        }
+        diag!("boot {boot}: back from {what}");
+    }
+    match STEPS.get(boot) {
+        Some(&(reset_type, reset_reason, what)) => {
+            // 哨兵每次启动都前进，不会无限重启
+            sentinel.record(boot as u64 + 1, reset_type, reset_reason);
+            diag!("boot {boot}: {what}");
+            let ret = sbi::system_reset(reset_type, reset_reason);
+            sentinel.magic = 0;
+            diag!("system_reset returned error {}", ret.error);
+            TestOutcome::Fail
+        }
+        None => {
+            sentinel.magic = 0;
+            diag!("completed {} resets", STEPS.len());
+            TestOutcome::Pass
+        }
+    }
+}
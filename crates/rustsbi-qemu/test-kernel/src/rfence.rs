@@ -0,0 +1,16 @@
+use crate::{
+    sbi,
+    suite::{Env, TestOutcome},
+};
+
+/// 以空掩码调用 `remote_fence_i`：规范规定这是合法的空操作，应当直接返回成功。
+pub(crate) fn test_rfence_empty(_env: &Env) -> TestOutcome {
+    require_ext!(RFNC, "rfence_empty");
+    // 注意 hart_mask_base 为 usize::MAX 表示所有核，这里使用 0
+    let ret = sbi::remote_fence_i(0, 0);
+    diag!("remote_fence_i(0, 0) returned error {}", ret.error);
+    match ret.result() {
+        Ok(_) => TestOutcome::Pass,
+        Err(_) => TestOutcome::Fail,
+    }
+}
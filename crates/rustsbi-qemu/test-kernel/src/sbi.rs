@@ -0,0 +1,396 @@
+//! 简化的 SBI 接口。
+
+use core::{arch::asm, fmt};
+use spin::Once;
+
+/// 扩展号。
+pub(crate) mod eid {
+    pub const BASE: usize = 0x10;
+    pub const TIME: usize = 0x5449_4D45;
+    pub const SPI: usize = 0x0073_5049;
+    pub const RFNC: usize = 0x5246_4E43;
+    pub const HSM: usize = 0x0048_534D;
+    pub const SRST: usize = 0x5352_5354;
+    pub const PMU: usize = 0x0050_4D55;
+    pub const DBCN: usize = 0x4442_434E;
+    pub const FWFT: usize = 0x4657_4654;
+}
+
+/// 启动时探测一次的 SBI 实现信息。
+pub struct SbiInfo {
+    pub spec_version: usize,
+    pub impl_id: usize,
+    pub impl_version: usize,
+    pub ids: MachineIds,
+    /// 已知扩展及其探测结果。
+    extensions: [(usize, bool); 8],
+}
+
+impl SbiInfo {
+    /// 所有扩展都不可用的信息。
+    pub const fn empty() -> Self {
+        use eid::*;
+        Self {
+            spec_version: 0,
+            impl_id: 0,
+            impl_version: 0,
+            ids: MachineIds {
+                mvendorid: 0,
+                marchid: 0,
+                mimpid: 0,
+            },
+            extensions: [
+                (TIME, false),
+                (SPI, false),
+                (RFNC, false),
+                (HSM, false),
+                (SRST, false),
+                (PMU, false),
+                (DBCN, false),
+                (FWFT, false),
+            ],
+        }
+    }
+
+    /// 通过基础扩展探测实现信息和各扩展。
+    fn probe() -> Self {
+        let mut ans = Self::empty();
+        ans.spec_version = get_spec_version();
+        ans.impl_id = base_call(1);
+        ans.impl_version = base_call(2);
+        ans.ids = machine_ids();
+        for (extension, available) in &mut ans.extensions {
+            *available = probe_extension(*extension);
+        }
+        ans
+    }
+
+    /// 规范版本是否不低于 `major.minor`。
+    pub fn spec_at_least(&self, major: usize, minor: usize) -> bool {
+        let version = (self.spec_version >> 24) & 0x7f;
+        (version, self.spec_version & 0xff_ffff) >= (major, minor)
+    }
+
+    /// 扩展是否可用，未知扩展视为不可用。
+    pub fn has(&self, extension: usize) -> bool {
+        self.extensions
+            .iter()
+            .any(|&(e, available)| e == extension && available)
+    }
+}
+
+/// 机器的 `mvendorid`、`marchid` 和 `mimpid`。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MachineIds {
+    pub mvendorid: usize,
+    pub marchid: usize,
+    pub mimpid: usize,
+}
+
+/// 通过基础扩展一次读出三个机器编号寄存器。
+pub fn machine_ids() -> MachineIds {
+    MachineIds {
+        mvendorid: base_call(4),
+        marchid: base_call(5),
+        mimpid: base_call(6),
+    }
+}
+
+/// 已登记的 SBI 实现名称。
+fn impl_name(impl_id: usize) -> &'static str {
+    match impl_id {
+        0 => "BBL",
+        1 => "OpenSBI",
+        2 => "Xvisor",
+        3 => "KVM",
+        4 => "RustSBI",
+        5 => "Diosix",
+        6 => "Coffer",
+        7 => "Xen Project",
+        8 => "PolarFire HSS",
+        9 => "coreboot",
+        10 => "oreboot",
+        11 => "bhyve",
+        _ => "unknown",
+    }
+}
+
+/// 固件指纹：规范版本、实现和机器编号。
+impl fmt::Display for SbiInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let major = (self.spec_version >> 24) & 0x7f;
+        let minor = self.spec_version & 0xff_ffff;
+        writeln!(f, "[test-kernel] SBI specification v{major}.{minor}")?;
+        writeln!(
+            f,
+            "[test-kernel] SBI implementation {} (id {}) version {:#x}",
+            impl_name(self.impl_id),
+            self.impl_id,
+            self.impl_version
+        )?;
+        write!(
+            f,
+            "[test-kernel] mvendorid {:#x} marchid {:#x} mimpid {:#x}",
+            self.ids.mvendorid, self.ids.marchid, self.ids.mimpid
+        )
+    }
+}
+
+/// 在测试开始前打印固件指纹。
+pub fn print_firmware_info() {
+    println!("{}", info());
+}
+
+/// 探测过的 SBI 实现信息。
+pub fn info() -> &'static SbiInfo {
+    static INFO: Once<SbiInfo> = Once::new();
+    INFO.call_once(SbiInfo::probe)
+}
+
+/// 固件实现的 SBI 规范版本。
+#[inline]
+pub fn get_spec_version() -> usize {
+    base_call(0)
+}
+
+#[inline]
+fn base_call(fid: usize) -> usize {
+    ecall(eid::BASE, fid, [0; 6]).value
+}
+
+pub const SHUTDOWN: u32 = 0;
+pub const COLD_REBOOT: u32 = 1;
+pub const WARM_REBOOT: u32 = 2;
+pub const NO_REASON: u32 = 0;
+pub const SYSTEM_FAILURE: u32 = 1;
+
+/// 不支持请求的操作。
+pub const ERR_NOT_SUPPORTED: usize = -2isize as usize;
+/// 拒绝请求的操作。
+pub const ERR_DENIED: usize = -4isize as usize;
+
+/// SBI 调用的返回值。
+#[derive(Clone, Copy, Debug)]
+pub struct SbiRet {
+    pub error: usize,
+    pub value: usize,
+}
+
+impl SbiRet {
+    /// 成功时得到返回值，失败时得到错误码。
+    #[inline]
+    pub fn result(self) -> Result<usize, usize> {
+        match self.error {
+            0 => Ok(self.value),
+            e => Err(e),
+        }
+    }
+}
+
+/// 以 6 个参数发起 SBI 调用。
+#[inline(always)]
+pub fn ecall(eid: usize, fid: usize, args: [usize; 6]) -> SbiRet {
+    let (error, value);
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a6") fid,
+            in("a7") eid,
+            options(nostack),
+        );
+    }
+    SbiRet { error, value }
+}
+
+/// 实验性扩展的 EID 范围。
+pub const EXPERIMENTAL_EIDS: core::ops::RangeInclusive<usize> = 0x0800_0000..=0x08FF_FFFF;
+/// 厂商扩展的 EID 范围。
+pub const VENDOR_EIDS: core::ops::RangeInclusive<usize> = 0x0900_0000..=0x09FF_FFFF;
+
+/// 调用实验性或厂商扩展，用来测试 RustSBI 的自定义扩展。
+///
+/// 只接受 [`EXPERIMENTAL_EIDS`] 和 [`VENDOR_EIDS`] 中的 EID，其他 EID 不发起调用，返回 `None`。
+#[inline]
+pub fn vendor_call(eid: usize, fid: usize, args: [usize; 6]) -> Option<SbiRet> {
+    if EXPERIMENTAL_EIDS.contains(&eid) || VENDOR_EIDS.contains(&eid) {
+        Some(ecall(eid, fid, args))
+    } else {
+        None
+    }
+}
+
+/// 探测扩展是否可用。
+#[inline]
+pub fn probe_extension(extension: usize) -> bool {
+    matches!(ecall(eid::BASE, 3, [extension, 0, 0, 0, 0, 0]).result(), Ok(v) if v != 0)
+}
+
+/// 设置本核下一次 S 态时钟中断的时刻。
+///
+/// 和 [`console_write`] 一样，RV32 上 64 位的时刻拆成 `a0` 和 `a1` 两半传递。
+#[inline]
+pub fn set_timer(stime_value: u64) -> SbiRet {
+    let (lo, hi) = split_u64(stime_value);
+    ecall(eid::TIME, 0, [lo, hi, 0, 0, 0, 0])
+}
+
+/// 向 `hart_mask` 和 `hart_mask_base` 选中的硬件线程发送 S 态软件中断。
+#[inline]
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    ecall(eid::SPI, 0, [hart_mask, hart_mask_base, 0, 0, 0, 0])
+}
+
+/// 让 `hart_mask` 和 `hart_mask_base` 选中的硬件线程执行 `fence.i`。
+#[inline]
+pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    ecall(eid::RFNC, 0, [hart_mask, hart_mask_base, 0, 0, 0, 0])
+}
+
+/// 硬件线程已停止，`hart_get_status` 的返回值。
+pub const HART_STOPPED: usize = 1;
+
+/// 启动一个硬件线程，从 `start_addr` 以 `a0 = hartid`、`a1 = opaque` 开始执行。
+#[inline]
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> SbiRet {
+    ecall(eid::HSM, 0, [hartid, start_addr, opaque, 0, 0, 0])
+}
+
+/// 停止本核，成功则不会返回。
+#[inline]
+pub fn hart_stop() -> SbiRet {
+    ecall(eid::HSM, 1, [0; 6])
+}
+
+/// 查询硬件线程的状态。
+#[inline]
+pub fn hart_get_status(hartid: usize) -> SbiRet {
+    ecall(eid::HSM, 2, [hartid, 0, 0, 0, 0, 0])
+}
+
+/// PMU 计数器数目。
+#[inline]
+pub fn pmu_num_counters() -> SbiRet {
+    ecall(eid::PMU, 0, [0; 6])
+}
+
+/// PMU 计数器信息：CSR 编号、位宽减一和类型。
+#[inline]
+pub fn pmu_counter_get_info(counter_idx: usize) -> SbiRet {
+    ecall(eid::PMU, 1, [counter_idx, 0, 0, 0, 0, 0])
+}
+
+/// 在 `counter_idx_base` 和 `counter_idx_mask` 选中的计数器中找一个配置为统计 `event_idx`。
+#[inline]
+pub fn pmu_counter_config_matching(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    config_flags: usize,
+    event_idx: usize,
+    event_data: u64,
+) -> SbiRet {
+    ecall(
+        eid::PMU,
+        2,
+        [
+            counter_idx_base,
+            counter_idx_mask,
+            config_flags,
+            event_idx,
+            event_data as _,
+            0,
+        ],
+    )
+}
+
+/// 启动选中的计数器，`start_flags` 第 0 位置位时先把计数器设为 `initial_value`。
+#[inline]
+pub fn pmu_counter_start(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    start_flags: usize,
+    initial_value: u64,
+) -> SbiRet {
+    let (lo, hi) = split_u64(initial_value);
+    ecall(
+        eid::PMU,
+        3,
+        [counter_idx_base, counter_idx_mask, start_flags, lo, hi, 0],
+    )
+}
+
+/// 停止选中的计数器。
+#[inline]
+pub fn pmu_counter_stop(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    stop_flags: usize,
+) -> SbiRet {
+    ecall(
+        eid::PMU,
+        4,
+        [counter_idx_base, counter_idx_mask, stop_flags, 0, 0, 0],
+    )
+}
+
+/// 设置固件特性 `feature` 的值。
+#[inline]
+pub fn fwft_set(feature: u32, value: usize, flags: usize) -> SbiRet {
+    ecall(eid::FWFT, 0, [feature as _, value, flags, 0, 0, 0])
+}
+
+/// 读取固件特性 `feature` 的值。
+#[inline]
+pub fn fwft_get(feature: u32) -> SbiRet {
+    ecall(eid::FWFT, 1, [feature as _, 0, 0, 0, 0, 0])
+}
+
+/// 通过调试控制台输出 `bytes`，成功时返回实际写出的字节数。
+///
+/// 规范用 `base_addr_lo` 和 `base_addr_hi` 两个参数传递缓冲区的物理地址：
+/// RV64 上低位参数就是完整地址，高位为 0；RV32 的物理地址可能超过 32 位（Sv32 为 34 位），
+/// 必须拆成两半，只传一个参数会截断地址。内核不开分页，缓冲区的地址就是物理地址。
+#[inline]
+pub fn console_write(bytes: &[u8]) -> SbiRet {
+    let (lo, hi) = split_u64(bytes.as_ptr() as usize as u64);
+    ecall(eid::DBCN, 0, [bytes.len(), lo, hi, 0, 0, 0])
+}
+
+/// 从调试控制台读取输入到 `buf`，成功时返回实际读到的字节数，没有输入时为 0。
+///
+/// 缓冲区地址的传递方式与 [`console_write`] 相同。
+#[inline]
+pub fn console_read(buf: &mut [u8]) -> SbiRet {
+    let (lo, hi) = split_u64(buf.as_mut_ptr() as usize as u64);
+    ecall(eid::DBCN, 1, [buf.len(), lo, hi, 0, 0, 0])
+}
+
+/// 把 64 位参数拆成低位和高位两个参数，RV64 上高位总是 0。
+#[inline]
+fn split_u64(val: u64) -> (usize, usize) {
+    if cfg!(target_pointer_width = "64") {
+        (val as usize, 0)
+    } else {
+        (val as u32 as usize, (val >> 32) as usize)
+    }
+}
+
+/// 通过调试控制台输出一个字节。
+#[inline]
+pub fn console_write_byte(byte: u8) -> SbiRet {
+    ecall(eid::DBCN, 2, [byte as _, 0, 0, 0, 0, 0])
+}
+
+/// 复位系统，成功则不会返回。
+pub fn system_reset(reset_type: u32, reset_reason: u32) -> SbiRet {
+    ecall(
+        eid::SRST,
+        0,
+        [reset_type as _, reset_reason as _, 0, 0, 0, 0],
+    )
+}
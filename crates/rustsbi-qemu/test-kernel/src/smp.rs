@@ -0,0 +1,219 @@
+//! 多核测试支持。
+
+use crate::{sbi, suite::Env, watchdog::Watchdog, BoardInfo};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// 支持的最大硬件线程数，qemu-virt 最多 8 个。
+pub(crate) const MAX_HARTS: usize = 8;
+
+/// 在每个硬件线程上运行的测试，返回是否通过。
+pub(crate) type Job = fn(&Env, usize) -> bool;
+
+/// 硬件线程 `hartid` 在设备树 cpu 节点中的序号，用作按核存放数据的下标。
+///
+/// 硬件线程号可能不连续，不能直接当下标用；不在设备树中的硬件线程返回 `None`。
+pub(crate) fn slot(board: &BoardInfo, hartid: usize) -> Option<usize> {
+    board.hart_ids().iter().position(|&id| id == hartid)
+}
+
+/// 各硬件线程报告的测试结果，按 [`slot`] 存放。
+///
+/// 每个结果带着报告它的任务的代数，超时后才到达的从核报告的是旧的代数，不会混入之后的测试。
+pub(crate) struct HartResults {
+    generation: AtomicUsize,
+    slots: [AtomicUsize; MAX_HARTS],
+}
+
+const PENDING: usize = 0;
+const PASSED: usize = 1;
+const FAILED: usize = 2;
+
+impl HartResults {
+    const fn new() -> Self {
+        Self {
+            generation: AtomicUsize::new(0),
+            slots: [const { AtomicUsize::new(PENDING) }; MAX_HARTS],
+        }
+    }
+
+    fn reset(&self, generation: usize) {
+        self.generation.store(generation, Ordering::Relaxed);
+        for r in &self.slots {
+            r.store(generation << 2 | PENDING, Ordering::Relaxed);
+        }
+    }
+
+    /// 报告第 `generation` 代任务的结果，代数已经过时的报告被丢弃。
+    fn report(&self, generation: usize, slot: usize, passed: bool) {
+        let val = if passed { PASSED } else { FAILED };
+        let _ = self.slots[slot].compare_exchange(
+            generation << 2 | PENDING,
+            generation << 2 | val,
+            Ordering::Release,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// 序号为 `slot` 的硬件线程的结果，尚未报告时为 `None`。
+    pub fn get(&self, slot: usize) -> Option<bool> {
+        let r = self.slots[slot].load(Ordering::Acquire);
+        if r >> 2 != self.generation.load(Ordering::Relaxed) {
+            return None;
+        }
+        match r & 0b11 {
+            PENDING => None,
+            r => Some(r == PASSED),
+        }
+    }
+
+    /// 打印设备树中没有通过的硬件线程，全部通过时返回 `true`。
+    pub fn check(&self, board: &BoardInfo) -> bool {
+        let mut ans = true;
+        for (slot, &hartid) in board.hart_ids().iter().enumerate() {
+            match self.get(slot) {
+                Some(true) => {}
+                Some(false) => {
+                    diag!("hart {hartid} failed");
+                    ans = false;
+                }
+                None => {
+                    diag!("hart {hartid} did not report");
+                    ans = false;
+                }
+            }
+        }
+        ans
+    }
+}
+
+static RESULTS: HartResults = HartResults::new();
+
+/// 让多个核在同一时刻开始的屏障，每次使用前要 [`Barrier::reset`]。
+pub(crate) struct Barrier(AtomicUsize);
+
+impl Barrier {
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    pub fn reset(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+
+    /// 到达屏障并等待共 `count` 个核到达，看门狗到期时返回 `false`。
+    pub fn wait(&self, count: usize, watchdog: &Watchdog) -> bool {
+        self.0.fetch_add(1, Ordering::AcqRel);
+        while self.0.load(Ordering::Acquire) < count {
+            if watchdog.expired() {
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+        true
+    }
+}
+
+/// 传给从核的任务。
+///
+/// 放在静态变量里而不是启动它的核的栈上：看门狗到期后启动它的核就返回了，
+/// 之后才启动的从核不能再访问那个栈帧。从核通过 `opaque` 得到代数，和这里的不同就直接停止。
+#[derive(Clone, Copy)]
+struct Task {
+    generation: usize,
+    env: Env,
+    job: Job,
+}
+
+/// 当前的任务，`None` 表示还没有运行过。
+static TASK: Mutex<Option<Task>> = Mutex::new(None);
+/// 最近一次任务的代数。
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// 登记一个新任务，返回它的代数，作为 `opaque` 传给从核。
+fn publish(env: &Env, job: Job) -> usize {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    *TASK.lock() = Some(Task {
+        generation,
+        env: *env,
+        job,
+    });
+    RESULTS.reset(generation);
+    generation
+}
+
+/// 通过 HSM 启动所有从核，和本核一起运行 `job`，等待各核报告结果并停止。
+pub(crate) fn run_on_all_harts(env: &Env, job: Job) -> &'static HartResults {
+    let secondaries = || {
+        env.board
+            .hart_ids()
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, id)| id != env.hartid)
+    };
+    let generation = publish(env, job);
+    for (slot, hartid) in secondaries() {
+        if sbi::hart_start(hartid, entry(), generation)
+            .result()
+            .is_err()
+        {
+            RESULTS.report(generation, slot, false);
+        }
+    }
+    if let Some(slot) = slot(env.board, env.hartid) {
+        RESULTS.report(generation, slot, job(env, env.hartid));
+    }
+    // 最多等待 1 秒
+    let watchdog = Watchdog::new(env.board.frequency, 1000);
+    for (slot, hartid) in secondaries() {
+        wait_stopped(hartid, slot, &watchdog);
+    }
+    &RESULTS
+}
+
+/// 通过 HSM 只启动从核 `hartid` 运行 `job`，等待它报告结果并停止。
+///
+/// `hartid` 不在设备树中、启动失败，或者 `watchdog` 到期时从核还没有报告并停止，返回 `None`。
+pub(crate) fn run_on_hart(env: &Env, hartid: usize, job: Job, watchdog: &Watchdog) -> Option<bool> {
+    let slot = slot(env.board, hartid)?;
+    let generation = publish(env, job);
+    sbi::hart_start(hartid, entry(), generation).result().ok()?;
+    if wait_stopped(hartid, slot, watchdog) {
+        RESULTS.get(slot)
+    } else {
+        None
+    }
+}
+
+/// 从核的入口地址。
+fn entry() -> usize {
+    crate::secondary_start as *const () as usize
+}
+
+/// 等待从核报告结果并进入停止状态，看门狗到期时返回 `false`。
+fn wait_stopped(hartid: usize, slot: usize, watchdog: &Watchdog) -> bool {
+    while RESULTS.get(slot).is_none()
+        || sbi::hart_get_status(hartid).result() != Ok(sbi::HART_STOPPED)
+    {
+        if watchdog.expired() {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    true
+}
+
+/// 从核的 rust 入口。
+pub(crate) extern "C" fn secondary_main(hartid: usize, opaque: usize) -> ! {
+    crate::trap::install();
+    let task = *TASK.lock();
+    // 启动它的任务已经超时结束时直接停止；只会启动设备树中的硬件线程
+    if let Some(task) = task.filter(|task| task.generation == opaque) {
+        if let Some(slot) = slot(task.env.board, hartid) {
+            RESULTS.report(opaque, slot, (task.job)(&task.env, hartid));
+        }
+    }
+    sbi::hart_stop();
+    unreachable!()
+}
@@ -0,0 +1,43 @@
+//! 浸泡测试：反复运行整个测试套件，暴露偶发的竞争和资源泄漏。
+
+use crate::{
+    suite::{self, Env},
+    watchdog::Watchdog,
+};
+
+/// 每轮测试的时限。
+const ITERATION_MILLIS: u64 = 60_000;
+
+/// 运行的轮数，取自编译时的 `SOAK_ITERATIONS` 环境变量；未设置或为 0 时一直运行。
+fn iterations() -> Option<u64> {
+    match option_env!("SOAK_ITERATIONS").map(str::parse) {
+        Some(Ok(0)) | None => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => panic!("SOAK_ITERATIONS is not a number"),
+    }
+}
+
+/// 反复运行测试套件，直到第一次失败或完成指定轮数，所有轮次都通过时返回 `true`。
+///
+/// 每轮有独立的看门狗，一轮耗时超过 [`ITERATION_MILLIS`] 也算失败。
+pub(crate) fn run(env: &Env) -> bool {
+    let limit = iterations();
+    let mut completed = 0u64;
+    let passed = loop {
+        if limit.is_some_and(|limit| completed >= limit) {
+            break true;
+        }
+        note!("soak iteration {}", completed + 1);
+        let watchdog = Watchdog::new(env.board.frequency, ITERATION_MILLIS);
+        if !suite::run_all(env) {
+            break false;
+        }
+        if watchdog.expired() {
+            diag!("iteration took more than {ITERATION_MILLIS} ms");
+            break false;
+        }
+        completed += 1;
+    };
+    note!("soak completed {completed} iterations");
+    passed
+}
@@ -0,0 +1,369 @@
+use crate::{
+    clint, console, ecall, fwft, hsm, ipi, irq, pmu, reset, rfence,
+    sbi::{MachineIds, SbiInfo},
+    timer, trap, BoardInfo, StringInline,
+};
+use aclint::SifiveClint;
+use core::fmt;
+
+/// 打印测试的诊断信息。打开 `tap-output` 特性时以 `# ` 开头，是 TAP 的注释行。
+macro_rules! diag {
+    ($($arg:tt)*) => {
+        $crate::suite::print_diag("[test-kernel]   ", format_args!($($arg)*))
+    };
+}
+
+/// 同 `diag!`，用于测试之外的启动和总结信息。
+macro_rules! note {
+    ($($arg:tt)*) => {
+        $crate::suite::print_diag("[test-kernel] ", format_args!($($arg)*))
+    };
+}
+
+/// 要求 SBI 扩展可用，否则打印 SKIP 并从所在的测试函数返回 [`TestOutcome::Skip`]。
+///
+/// 默认查询 [`crate::sbi::info`]，也可以用 `require_ext!(in info, ...)` 指定信息。
+macro_rules! require_ext {
+    ($ext:ident, $what:expr) => {
+        require_ext!(in $crate::sbi::info(), $ext, $what)
+    };
+    (in $info:expr, $ext:ident, $what:expr) => {
+        if !$info.has($crate::sbi::eid::$ext) {
+            diag!(
+                "SKIP {}: {} extension unavailable",
+                $what,
+                stringify!($ext)
+            );
+            return $crate::suite::TestOutcome::Skip;
+        }
+    };
+}
+
+/// 单项测试的结果。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum TestOutcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// 测试运行环境。
+#[derive(Clone, Copy)]
+pub(crate) struct Env {
+    /// 运行测试的硬件线程。
+    pub hartid: usize,
+    pub board: &'static BoardInfo,
+    /// 已确认可用的 CLINT，直接访问寄存器的测试依赖它。
+    pub clint: Option<&'static SifiveClint>,
+}
+
+/// 一项测试。
+pub(crate) struct Test {
+    pub name: &'static str,
+    pub run: fn(&Env) -> TestOutcome,
+}
+
+/// 所有测试，按顺序运行。
+pub(crate) const TESTS: &[Test] = &[
+    // 会重启系统，放在最前面
+    Test {
+        name: "reset_reasons",
+        run: reset::test_reset_reasons,
+    },
+    Test {
+        name: "trap_clobber",
+        run: trap::test_trap_clobber,
+    },
+    Test {
+        name: "irq_restore",
+        run: irq::test_irq_restore,
+    },
+    Test {
+        name: "delegation",
+        run: trap::test_delegation,
+    },
+    Test {
+        name: "for_each_hart",
+        run: clint::test_for_each_hart,
+    },
+    Test {
+        name: "clock_conversion",
+        run: timer::test_clock_conversion,
+    },
+    Test {
+        name: "timer_sbi_vs_direct",
+        run: timer::test_timer_sbi_vs_direct,
+    },
+    Test {
+        name: "schedule_after_us",
+        run: timer::test_schedule_after_us,
+    },
+    Test {
+        name: "timer_mie_off",
+        run: timer::test_timer_mie_off,
+    },
+    Test {
+        name: "handle_oneshot",
+        run: timer::test_handle_oneshot,
+    },
+    Test {
+        name: "rdtime",
+        run: timer::test_rdtime,
+    },
+    Test {
+        name: "timer_full",
+        run: timer::test_timer_full,
+    },
+    Test {
+        name: "timer_drift",
+        run: timer::test_timer_drift,
+    },
+    Test {
+        name: "timer_stip_clear_smp",
+        run: timer::test_timer_stip_clear_smp,
+    },
+    Test {
+        name: "csr_preservation",
+        run: ecall::test_csr_preservation,
+    },
+    Test {
+        name: "machine_ids",
+        run: ecall::test_machine_ids,
+    },
+    Test {
+        name: "probe_consistency",
+        run: ecall::test_probe_consistency,
+    },
+    Test {
+        name: "vendor_noop",
+        run: ecall::test_vendor_noop,
+    },
+    Test {
+        name: "concurrent_ecall",
+        run: ecall::test_concurrent_ecall,
+    },
+    Test {
+        name: "ecall_from_handler",
+        run: ecall::test_ecall_from_handler,
+    },
+    Test {
+        name: "hsm_cycle",
+        run: hsm::test_hsm_cycle,
+    },
+    Test {
+        name: "ipi_self",
+        run: ipi::test_ipi_self,
+    },
+    Test {
+        name: "ipi_broadcast_latency",
+        run: ipi::test_ipi_broadcast_latency,
+    },
+    Test {
+        name: "spurious_irq",
+        run: ipi::test_spurious_irq,
+    },
+    Test {
+        name: "dbcn_loopback",
+        run: console::test_dbcn_loopback,
+    },
+    Test {
+        name: "dbcn_write_pages",
+        run: console::test_dbcn_write_pages,
+    },
+    Test {
+        name: "dbcn_echo",
+        run: console::test_dbcn_echo,
+    },
+    Test {
+        name: "ecall_edge_args",
+        run: console::test_ecall_edge_args,
+    },
+    Test {
+        name: "pmu_overflow",
+        run: pmu::test_pmu_overflow,
+    },
+    Test {
+        name: "rfence_empty",
+        run: rfence::test_rfence_empty,
+    },
+    Test {
+        name: "fwft",
+        run: fwft::test_fwft,
+    },
+    Test {
+        name: "bench_ecall",
+        run: ecall::bench_ecall,
+    },
+    Test {
+        name: "require_ext_skips",
+        run: test_require_ext_skips,
+    },
+    Test {
+        name: "isa",
+        run: test_isa,
+    },
+    Test {
+        name: "firmware_info_format",
+        run: test_firmware_info_format,
+    },
+    Test {
+        name: "tap_line",
+        run: test_tap_line,
+    },
+];
+
+/// 依次运行所有测试，没有测试失败时返回 `true`。
+///
+/// 打开 `tap-output` 特性时按 Test Anything Protocol 输出结果。
+pub(crate) fn run_all(env: &Env) -> bool {
+    let mut failed = 0;
+    if cfg!(feature = "tap-output") {
+        println!("1..{}", TESTS.len());
+    }
+    for (i, test) in TESTS.iter().enumerate() {
+        let outcome = (test.run)(env);
+        // 不把测试打开的中断留给下一项测试
+        irq::IRQS.restore(env.hartid);
+        if cfg!(feature = "tap-output") {
+            println!("{}", TapLine(i + 1, test.name, outcome));
+        } else {
+            println!("[test-kernel] {:<24} {outcome:?}", test.name);
+        }
+        if outcome == TestOutcome::Fail {
+            failed += 1;
+        }
+    }
+    failed == 0
+}
+
+/// `diag!` 和 `note!` 的实现：默认加上 `prefix`，TAP 输出时改为 `# `。
+pub(crate) fn print_diag(prefix: &str, args: fmt::Arguments) {
+    if cfg!(feature = "tap-output") {
+        println!("# {args}");
+    } else {
+        println!("{prefix}{args}");
+    }
+}
+
+/// 一行 TAP 测试结果：序号、测试名、结果。
+struct TapLine(usize, &'static str, TestOutcome);
+
+impl fmt::Display for TapLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(number, name, outcome) = self;
+        match outcome {
+            TestOutcome::Pass => write!(f, "ok {number} - {name}"),
+            TestOutcome::Fail => write!(f, "not ok {number} - {name}"),
+            TestOutcome::Skip => write!(f, "ok {number} - {name} # SKIP"),
+        }
+    }
+}
+
+/// 确认扩展标记为不可用时 [`require_ext`] 会跳过测试。
+fn test_require_ext_skips(_env: &Env) -> TestOutcome {
+    fn guarded(info: &SbiInfo) -> TestOutcome {
+        require_ext!(in info, TIME, "require_ext self-check");
+        TestOutcome::Fail
+    }
+    match guarded(&SbiInfo::empty()) {
+        TestOutcome::Skip => TestOutcome::Pass,
+        _ => TestOutcome::Fail,
+    }
+}
+
+/// 定长的格式化缓冲区，供检查格式化输出的测试使用，写满时报错。
+struct FmtBuf<const N: usize>(usize, [u8; N]);
+
+impl<const N: usize> FmtBuf<N> {
+    const fn new() -> Self {
+        Self(0, [0; N])
+    }
+
+    fn as_str(&self) -> &str {
+        // 只写入过完整的 &str
+        core::str::from_utf8(&self.1[..self.0]).unwrap()
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.0 + s.len();
+        let dst = self.1.get_mut(self.0..end).ok_or(fmt::Error)?;
+        dst.copy_from_slice(s.as_bytes());
+        self.0 = end;
+        Ok(())
+    }
+}
+
+/// 确认 [`TapLine`] 把三种结果格式化成 TAP 的写法。
+fn test_tap_line(_env: &Env) -> TestOutcome {
+    const CASES: [(TestOutcome, &str); 3] = [
+        (TestOutcome::Pass, "ok 1 - x"),
+        (TestOutcome::Fail, "not ok 1 - x"),
+        (TestOutcome::Skip, "ok 1 - x # SKIP"),
+    ];
+    let mut ans = TestOutcome::Pass;
+    for (outcome, expected) in CASES {
+        let mut buf = FmtBuf::<32>::new();
+        let written = fmt::write(&mut buf, format_args!("{}", TapLine(1, "x", outcome)));
+        if written.is_err() || buf.as_str() != expected {
+            diag!(
+                "{outcome:?} formatted as {:?}, expected {expected:?}",
+                buf.as_str()
+            );
+            ans = TestOutcome::Fail;
+        }
+    }
+    ans
+}
+
+/// 确认 `riscv,isa` 的解析：示例字符串去掉结尾的 `\0`，超长字符串截断并被标记；
+/// 本机设备树给出的字符串以内核的字长开头（`rv64` 或 `rv32`），并且没有被截断。
+fn test_isa(env: &Env) -> TestOutcome {
+    let mut sample = StringInline::<16>::new();
+    sample.copy_from(b"rv64imafdc\0");
+    let sample_ok = sample.as_str() == "rv64imafdc" && !sample.truncated();
+    sample.copy_from(b"rv64imafdc_zicsr_zifencei\0");
+    let truncated_ok = sample.as_str() == "rv64imafdc_zicsr" && sample.truncated();
+    if !(sample_ok && truncated_ok) {
+        diag!("sample isa parsed as {:?}", sample.as_str());
+        return TestOutcome::Fail;
+    }
+
+    let isa = &env.board.isa;
+    if isa.as_str().is_empty() {
+        diag!("SKIP isa: riscv,isa not found in device tree");
+        return TestOutcome::Skip;
+    }
+    diag!("boot hart isa {isa}, truncated {}", isa.truncated());
+    let xlen = if usize::BITS == 64 { "rv64" } else { "rv32" };
+    if isa.as_str().starts_with(xlen) && !isa.truncated() {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 用一份填好的 [`SbiInfo`] 确认固件指纹的格式。
+fn test_firmware_info_format(_env: &Env) -> TestOutcome {
+    const EXPECTED: &str = "[test-kernel] SBI specification v2.0
+[test-kernel] SBI implementation RustSBI (id 4) version 0x10000
+[test-kernel] mvendorid 0x0 marchid 0x1 mimpid 0x2";
+    let mut info = SbiInfo::empty();
+    info.spec_version = 2 << 24;
+    info.impl_id = 4;
+    info.impl_version = 0x10000;
+    info.ids = MachineIds {
+        mvendorid: 0,
+        marchid: 1,
+        mimpid: 2,
+    };
+    let mut buf = FmtBuf::<256>::new();
+    let written = fmt::write(&mut buf, format_args!("{info}"));
+    if written.is_ok() && buf.as_str() == EXPECTED {
+        TestOutcome::Pass
+    } else {
+        diag!("formatted as:\n{}", buf.as_str());
+        TestOutcome::Fail
+    }
+}
@@ -0,0 +1,482 @@
+use crate::{
+    irq::{IrqSource, IRQS},
+    sbi::{self, SbiRet},
+    smp,
+    suite::{Env, TestOutcome},
+    trap,
+    watchdog::Watchdog,
+};
+use aclint::{nth_period_deadline, SifiveClint, TimebaseFrequency, DEFAULT_MAX_SPINS};
+use core::sync::atomic::{compiler_fence, AtomicUsize, Ordering};
+use riscv::register::{sie, sip, sstatus, time};
+
+/// 通过 SBI 设置时钟，再直接读 CLINT，确认 RustSBI 把请求的时刻原样写进了本核的 MTIMECMP。
+pub(crate) fn test_timer_sbi_vs_direct(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "timer_sbi_vs_direct");
+    let Some(clint) = env.clint else {
+        return TestOutcome::Skip;
+    };
+    // 10 秒后的时刻，保证读回之前不会触发
+    let requested = clint.read_mtime() + env.board.frequency * 10;
+    if let Err(e) = sbi::set_timer(requested).result() {
+        diag!("set_timer failed with error {e}");
+        return TestOutcome::Fail;
+    }
+    let observed = clint.read_mtimecmp(env.hartid);
+    sbi::set_timer(u64::MAX);
+    diag!("requested mtimecmp {requested:#x}, observed {observed:#x}");
+    if observed == requested {
+        TestOutcome::Pass
+    } else {
+        diagnose_timer(clint, env.hartid);
+        TestOutcome::Fail
+    }
+}
+
+/// 所有核同时：等本核时钟触发，再通过 SBI 设置到很远的时刻，
+/// 确认 STIP 被清除，并且 CLINT 上本核的 MTIMECMP 已经更新。
+pub(crate) fn test_timer_stip_clear_smp(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "timer_stip_clear_smp");
+    require_ext!(HSM, "timer_stip_clear_smp");
+    if env.clint.is_none() {
+        return TestOutcome::Skip;
+    }
+    if env.board.smp < 2 {
+        diag!("SKIP timer_stip_clear_smp: single hart");
+        return TestOutcome::Skip;
+    }
+    if smp::run_on_all_harts(env, stip_clears).check(env.board) {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+fn stip_clears(env: &Env, hartid: usize) -> bool {
+    let clint = env.clint.unwrap();
+    let now = clint.read_mtime();
+    sbi::set_timer(now);
+    if !(0..DEFAULT_MAX_SPINS).any(|_| sip::read().stimer()) {
+        diagnose_timer(clint, hartid);
+        return false;
+    }
+    let distant = now + env.board.frequency * 100;
+    sbi::set_timer(distant);
+    let cleared = !sip::read().stimer() && clint.read_mtimecmp(hartid) == distant;
+    sbi::set_timer(u64::MAX);
+    cleared
+}
+
+/// 把频率为 `from_hz` 的计数换算成频率为 `to_hz` 的计数，向下取整。
+///
+/// 任一频率未知（为 0）或结果超出 `u64` 时返回 `None`。
+pub(crate) fn convert_ticks(ticks: u64, from_hz: u64, to_hz: u64) -> Option<u64> {
+    if from_hz == 0 || to_hz == 0 {
+        return None;
+    }
+    (ticks as u128 * to_hz as u128 / from_hz as u128)
+        .try_into()
+        .ok()
+}
+
+/// 检查 MTIME 计数和 CPU 周期的换算：先用几组固定频率验证，再报告本机的换算结果。
+pub(crate) fn test_clock_conversion(env: &Env) -> TestOutcome {
+    const CASES: [(u64, u64, u64, Option<u64>); 5] = [
+        // 10 MHz 的 1 秒是 1 GHz 的 10^9 个周期
+        (10_000_000, 10_000_000, 1_000_000_000, Some(1_000_000_000)),
+        (1_000_000_000, 1_000_000_000, 10_000_000, Some(10_000_000)),
+        (3, 1_000_000, 3_000_000, Some(9)),
+        (u64::MAX, 1, 2, None),
+        (100, 10_000_000, 0, None),
+    ];
+    for (ticks, from, to, expected) in CASES {
+        let converted = convert_ticks(ticks, from, to);
+        if converted != expected {
+            diag!("{ticks} ticks at {from} Hz to {to} Hz: {converted:?}, expected {expected:?}");
+            return TestOutcome::Fail;
+        }
+    }
+    let board = env.board;
+    match board.mtime_ticks_to_cpu_cycles(board.frequency) {
+        Some(cycles) => {
+            diag!("1 s of mtime is {cycles} cpu cycles");
+            if board.cpu_cycles_to_mtime_ticks(cycles) != Some(board.frequency) {
+                return TestOutcome::Fail;
+            }
+        }
+        None => diag!("cpu clock-frequency unknown"),
+    }
+    TestOutcome::Pass
+}
+
+/// 前后都加 `fence` 读 MTIME，使读数不会和前后的访存交错，适合计时。
+#[inline]
+pub(crate) fn read_mtime_fenced(clint: &SifiveClint) -> u64 {
+    unsafe { core::arch::asm!("fence iorw, iorw") };
+    let mtime = clint.read_mtime();
+    unsafe { core::arch::asm!("fence iorw, iorw") };
+    mtime
+}
+
+/// 打印检查清单，说明 `hart_idx` 的时钟中断为什么没有触发。
+///
+/// 只能检查 S 态可见的状态；`mie.MTIE`、`mstatus.MIE` 和 `mip.MTIP` 属于 M 态，
+/// 由 MTIMECMP 是否已到期间接反映。`sie` 和 `sstatus` 是调用者所在核的 CSR。
+pub(crate) fn diagnose_timer(clint: &SifiveClint, hart_idx: usize) {
+    fn check(ok: bool) -> char {
+        if ok {
+            'x'
+        } else {
+            ' '
+        }
+    }
+    let mtime = clint.read_mtime();
+    let mtimecmp = clint.read_mtimecmp(hart_idx);
+    diag!("timer diagnosis for hart {hart_idx}:");
+    diag!(
+        "  [{}] mtimecmp {mtimecmp:#x} reached by mtime {mtime:#x}",
+        check(mtimecmp <= mtime)
+    );
+    diag!("  [{}] sip.STIP pending", check(sip::read().stimer()));
+    diag!("  [{}] sie.STIE enabled", check(sie::read().stimer()));
+    diag!("  [{}] sstatus.SIE enabled", check(sstatus::read().sie()));
+}
+
+/// 关闭 `sstatus.SIE` 时通过 SBI 设置一个已经到期的时钟，确认 STIP 挂起但没有进入处理函数；
+/// 再打开 `sstatus.SIE`，确认挂起的时钟中断立即送达。
+///
+/// `mstatus.MIE` 属于 M 态，S 态只能控制 `sstatus.SIE`。
+pub(crate) fn test_timer_mie_off(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "timer_mie_off");
+    let before = trap::STIMER.load(Ordering::Acquire);
+    unsafe { sstatus::clear_sie() };
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(time::read64());
+
+    let watchdog = Watchdog::new(env.board.frequency, 100);
+    while !sip::read().stimer() && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    let latched = sip::read().stimer();
+    let taken_while_off = trap::STIMER.load(Ordering::Acquire) != before;
+    diag!("SIE off: STIP latched {latched}, interrupt taken {taken_while_off}");
+
+    unsafe { sstatus::set_sie() };
+    // 打开 SIE 后的下一条指令之前就应该进入处理函数，不让编译器把读计数提前
+    compiler_fence(Ordering::SeqCst);
+    let delivered = trap::STIMER.load(Ordering::Acquire) != before;
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+    diag!("SIE on: delivered immediately {delivered}");
+
+    if latched && !taken_while_off && delivered {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 通过 SBI 设置 `micros` 微秒后的时钟，返回设置的时刻。
+///
+/// 请求比一个计数周期还短时不设置，返回 `None`。
+pub(crate) fn schedule_after_us(frequency: u64, micros: u64) -> Option<u64> {
+    let tick = TimebaseFrequency(frequency).min_representable_nanos();
+    if micros.saturating_mul(1000) < tick {
+        diag!("{micros} us is finer than one {tick} ns tick");
+        return None;
+    }
+    let deadline = time::read64() + frequency * micros / 1_000_000;
+    sbi::set_timer(deadline);
+    Some(deadline)
+}
+
+/// 确认 [`schedule_after_us`] 拒绝比一个计数周期还短的请求，并且设置的时钟会送达。
+pub(crate) fn test_schedule_after_us(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "schedule_after_us");
+    let frequency = env.board.frequency;
+    if schedule_after_us(frequency, 0).is_some() {
+        diag!("0 us request accepted");
+        sbi::set_timer(u64::MAX);
+        return TestOutcome::Fail;
+    }
+    let before = trap::STIMER.load(Ordering::Acquire);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    let scheduled = schedule_after_us(frequency, 1000).is_some();
+    let watchdog = Watchdog::new(frequency, 100);
+    while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+    let delivered = trap::STIMER.load(Ordering::Acquire) != before;
+    diag!("1000 us timer delivered {delivered}");
+    if scheduled && delivered {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// [`handle_oneshot`] 被调用的次数。
+pub(crate) static ONESHOT: AtomicUsize = AtomicUsize::new(0);
+/// [`handle_oneshot`] 的 SBI 调用返回后 STIP 仍然挂起的次数。
+pub(crate) static ONESHOT_STIP_LEFT: AtomicUsize = AtomicUsize::new(0);
+
+/// 一次性时钟的处理：通过 SBI 把本核的时钟设置到无穷远处，同时清除 STIP，时钟不再触发。
+///
+/// MTIMECMP 触发后保持原值，MTIME 回绕后会再次触发，所以一次性时钟必须在时钟中断处理函数中调用它。
+pub(crate) fn handle_oneshot() -> SbiRet {
+    let ret = sbi::set_timer(u64::MAX);
+    if sip::read().stimer() {
+        ONESHOT_STIP_LEFT.fetch_add(1, Ordering::Relaxed);
+    }
+    ONESHOT.fetch_add(1, Ordering::Relaxed);
+    ret
+}
+
+/// 先后设置两个 1 毫秒后的时钟，每次等中断送达后再等 10 毫秒，确认每个时钟都经过
+/// 处理函数中的 [`handle_oneshot`] 恰好处理一次，处理后 STIP 已经清除，之后没有再次触发。
+pub(crate) fn test_handle_oneshot(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "handle_oneshot");
+    let frequency = env.board.frequency;
+    if frequency == 0 {
+        diag!("SKIP handle_oneshot: timebase frequency unknown");
+        return TestOutcome::Skip;
+    }
+    let calls = ONESHOT.load(Ordering::Relaxed);
+    let stip_left = ONESHOT_STIP_LEFT.load(Ordering::Relaxed);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    let mut fired = [0; 2];
+    for count in &mut fired {
+        let before = ONESHOT.load(Ordering::Relaxed);
+        sbi::set_timer(time::read64() + frequency / 1000);
+        let watchdog = Watchdog::new(frequency, 100);
+        while ONESHOT.load(Ordering::Relaxed) == before && !watchdog.expired() {
+            core::hint::spin_loop();
+        }
+        // 给再次触发留出时间
+        let settle = Watchdog::new(frequency, 10);
+        while !settle.expired() {
+            core::hint::spin_loop();
+        }
+        *count = ONESHOT.load(Ordering::Relaxed) - before;
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+    let calls = ONESHOT.load(Ordering::Relaxed) - calls;
+    let stip_left = ONESHOT_STIP_LEFT.load(Ordering::Relaxed) - stip_left;
+    diag!("handler runs per deadline {fired:?}, STIP left pending {stip_left} of {calls}");
+    if fired == [1, 1] && stip_left == 0 {
+        TestOutcome::Pass
+    } else {
+        if let Some(clint) = env.clint {
+            diagnose_timer(clint, env.hartid);
+        }
+        TestOutcome::Fail
+    }
+}
+
+/// 通过 SBI 把时钟设置到上一个时刻之后一个周期，返回新的时刻。
+///
+/// 相对上一个时刻而不是当前时间计算，中断响应的延迟不会累积到之后的周期里。
+pub(crate) fn reschedule_periodic(last_deadline: u64, period: u64) -> u64 {
+    let deadline = last_deadline + period;
+    sbi::set_timer(deadline);
+    deadline
+}
+
+/// 用 [`reschedule_periodic`] 运行 1000 个 100 微秒的周期，把每次进入处理函数的时刻和从起点算起的
+/// 第 n 个周期比较：不能早于它，最大的延迟作为漂移报告，不能超过两个周期。
+pub(crate) fn test_timer_drift(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "timer_drift");
+    const PERIODS: u64 = 1000;
+    let period = env.board.frequency / 10_000;
+    if period == 0 {
+        diag!("SKIP timer_drift: timebase too slow");
+        return TestOutcome::Skip;
+    }
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    let start = time::read64();
+    let mut deadline = start;
+    let mut completed = 0;
+    let mut drift = 0;
+    let mut early = 0;
+    for n in 1..=PERIODS {
+        let before = trap::STIMER.load(Ordering::Acquire);
+        deadline = reschedule_periodic(deadline, period);
+        let watchdog = Watchdog::new(env.board.frequency, 100);
+        while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+            core::hint::spin_loop();
+        }
+        if trap::STIMER.load(Ordering::Acquire) == before {
+            break;
+        }
+        let entered = trap::STIMER_AT.load(Ordering::Relaxed);
+        let ideal = nth_period_deadline(start, period, n);
+        if entered < ideal {
+            early += 1;
+        }
+        drift = drift.max(entered.saturating_sub(ideal));
+        completed += 1;
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+
+    if completed != PERIODS {
+        diag!("timer stopped after {completed} of {PERIODS} periods");
+        return TestOutcome::Fail;
+    }
+    diag!("{PERIODS} periods of {period} ticks: max lateness {drift} ticks, {early} early");
+    if early == 0 && drift <= 2 * period {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 启动核最后的端到端检查：通过 SBI 设置 10 毫秒后的时钟，打开 `sie.STIE` 和 `sstatus.SIE`，
+/// 等陷入处理函数处理时钟中断，1 秒内没有送达时返回 `false`。
+pub(crate) fn round_trip(env: &Env) -> bool {
+    let frequency = env.board.frequency;
+    let before = trap::STIMER.load(Ordering::Acquire);
+    sbi::set_timer(time::read64() + frequency / 100);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    let watchdog = Watchdog::new(frequency, 1000);
+    while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+    let handled = trap::STIMER.load(Ordering::Acquire) != before;
+    if !handled {
+        note!("timer interrupt not received");
+    }
+    handled
+}
+
+/// 用 `rdtime` 读 `time` CSR，通过 SBI 设置 10 毫秒后的时钟并等待中断，再读一次，
+/// 确认 CSR 前进了大约等待的时长：不少于 10 毫秒，也不超过 20 毫秒。
+///
+/// `rdtime` 产生非法指令异常时跳过。
+pub(crate) fn test_rdtime(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "rdtime");
+    let frequency = env.board.frequency;
+    if frequency == 0 {
+        diag!("SKIP rdtime: timebase frequency unknown");
+        return TestOutcome::Skip;
+    }
+    let wait = frequency / 100;
+    let Some(start) = trap::probe_rdtime() else {
+        diag!("SKIP rdtime: rdtime raised illegal instruction");
+        return TestOutcome::Skip;
+    };
+    let before = trap::STIMER.load(Ordering::Acquire);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    sbi::set_timer(start + wait);
+    let watchdog = Watchdog::new(frequency, 1000);
+    while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+    let Some(end) = trap::probe_rdtime() else {
+        diag!("rdtime raised illegal instruction after the wait");
+        return TestOutcome::Fail;
+    };
+    if trap::STIMER.load(Ordering::Acquire) == before {
+        diag!("timer interrupt not received within 1 s");
+        return TestOutcome::Fail;
+    }
+    let advance = end.wrapping_sub(start);
+    diag!(
+        "rdtime advanced {advance} ticks over a {wait} tick wait ({} us)",
+        advance * 1_000_000 / frequency
+    );
+    if (wait..=2 * wait).contains(&advance) {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 测量一次完整的时钟中断各阶段的耗时并换算成纳秒：`set_timer` 调用本身、
+/// 调用返回到时钟触发的等待、时刻到达到进入处理函数的延迟、处理函数到主循环看到计数增加的延迟。
+///
+/// 固件处理请求的时间包含在调用耗时里，无法单独测量；有 CLINT 时用加 `fence` 的 MTIME 计时，
+/// 并确认 MTIMECMP 确实写入了请求的时刻。
+pub(crate) fn test_timer_full(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "timer_full");
+    let frequency = env.board.frequency;
+    if frequency == 0 {
+        diag!("SKIP timer_full: timebase frequency unknown");
+        return TestOutcome::Skip;
+    }
+    let now = || env.clint.map_or_else(time::read64, read_mtime_fenced);
+    let nanos = |ticks: u64| ticks as u128 * 1_000_000_000 / frequency as u128;
+
+    let before = trap::STIMER.load(Ordering::Acquire);
+    IRQS.enable(env.hartid, IrqSource::Timer);
+    unsafe { sstatus::set_sie() };
+    let issue = now();
+    // 1 毫秒后触发，足够调用返回
+    let deadline = issue + frequency / 1000;
+    let ret = sbi::set_timer(deadline);
+    let returned = now();
+    let mtimecmp = env.clint.map(|clint| clint.read_mtimecmp(env.hartid));
+    let watchdog = Watchdog::new(frequency, 100);
+    while trap::STIMER.load(Ordering::Acquire) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    let seen = now();
+    unsafe { sstatus::clear_sie() };
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    sbi::set_timer(u64::MAX);
+
+    if let Err(e) = ret.result() {
+        diag!("set_timer failed with error {e}");
+        return TestOutcome::Fail;
+    }
+    if trap::STIMER.load(Ordering::Acquire) == before {
+        diag!("timer interrupt not received within 100 ms");
+        return TestOutcome::Fail;
+    }
+    let entered = trap::STIMER_AT.load(Ordering::Relaxed);
+    let stages = [
+        ("set_timer ecall", returned - issue),
+        ("wait until fire", deadline.saturating_sub(returned)),
+        ("handler entry", entered.saturating_sub(deadline)),
+        ("handler to loop", seen.saturating_sub(entered)),
+    ];
+    for (stage, ticks) in stages {
+        diag!("{stage:<16} {ticks:>8} ticks {:>12} ns", nanos(ticks));
+    }
+    diag!(
+        "{:<16} {:>8} ticks {:>12} ns",
+        "total",
+        seen - issue,
+        nanos(seen - issue)
+    );
+    match mtimecmp {
+        // 读之前中断已经处理时，处理函数把 MTIMECMP 设置到了无穷远处
+        Some(mtimecmp) if mtimecmp != deadline && mtimecmp != u64::MAX => {
+            diag!("mtimecmp {mtimecmp:#x}, requested {deadline:#x}");
+            TestOutcome::Fail
+        }
+        _ if entered < deadline => {
+            diag!("handler entered before the deadline");
+            TestOutcome::Fail
+        }
+        _ => TestOutcome::Pass,
+    }
+}
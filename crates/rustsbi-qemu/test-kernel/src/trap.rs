@@ -0,0 +1,275 @@
+//! S 态陷入处理。
+
+use crate::{
+    irq::{IrqSource, IRQS},
+    pmu, sbi,
+    suite::{Env, TestOutcome},
+    timer,
+    watchdog::Watchdog,
+};
+use core::{
+    arch::{asm, global_asm, naked_asm},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+use riscv::register::{sip, sstatus, time};
+
+/// 陷入时保存的通用寄存器，`x[i]` 是 `xi`，`x[0]` 不使用。
+#[repr(C)]
+pub(crate) struct TrapFrame {
+    pub x: [usize; 32],
+}
+
+/// 处理过的 S 态软件中断数。
+pub(crate) static SSOFT: AtomicUsize = AtomicUsize::new(0);
+/// 进入处理函数时已经不再挂起的 S 态软件中断数。
+pub(crate) static SPURIOUS: AtomicUsize = AtomicUsize::new(0);
+/// 时钟中断处理函数中 `set_timer` 返回的错误码。
+pub(crate) static TIMER_ECALL_ERROR: AtomicUsize = AtomicUsize::new(0);
+/// 处理过的断点异常数。
+pub(crate) static BREAKPOINTS: AtomicUsize = AtomicUsize::new(0);
+/// 处理过的 S 态时钟中断数。
+pub(crate) static STIMER: AtomicUsize = AtomicUsize::new(0);
+/// 最近一次进入时钟中断处理函数时的 `time`。
+pub(crate) static STIMER_AT: AtomicU64 = AtomicU64::new(0);
+/// 探测期间跳过的非法指令异常数。
+pub(crate) static ILLEGAL_INSTRUCTIONS: AtomicUsize = AtomicUsize::new(0);
+/// 置位时下一次 S 态软件中断在 [`handle_ssoft`] 检查之前清除 SSIP，
+/// 模拟陷入之后、处理函数读 SSIP 之前中断源被撤销。
+pub(crate) static RETRACT_SSOFT: AtomicBool = AtomicBool::new(false);
+/// 置位时非法指令异常被跳过并计数，否则按意外陷入处理。
+static PROBING: AtomicBool = AtomicBool::new(false);
+
+const ILLEGAL_INSTRUCTION: usize = 2;
+const BREAKPOINT: usize = 3;
+const INTERRUPT: usize = 1 << (usize::BITS - 1);
+const SUPERVISOR_SOFT: usize = INTERRUPT | 1;
+const SUPERVISOR_TIMER: usize = INTERRUPT | 5;
+const COUNTER_OVERFLOW: usize = INTERRUPT | pmu::LCOFI;
+
+/// 在本核上安装陷入处理函数，直接模式。
+pub(crate) fn install() {
+    unsafe { asm!("csrw stvec, {}", in(reg) strap_entry as *const () as usize) };
+}
+
+// 陷入入口，在当前栈上保存全部通用寄存器。stvec 要求入口 4 字节对齐，所以不用裸函数。
+global_asm!(
+    "   .section .text.trap, \"ax\"
+        .align 2
+        .global strap_entry
+    strap_entry:
+        addi sp, sp, -32*8
+        sd   x1,   1*8(sp)
+        sd   x3,   3*8(sp)
+        sd   x4,   4*8(sp)
+        sd   x5,   5*8(sp)
+        addi x5, sp, 32*8
+        sd   x5,   2*8(sp)
+        .irp n, 6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        sd   x\\n, \\n*8(sp)
+        .endr
+        mv   a0, sp
+        call {handler}
+        ld   x1,   1*8(sp)
+        ld   x3,   3*8(sp)
+        ld   x4,   4*8(sp)
+        .irp n, 5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        ld   x\\n, \\n*8(sp)
+        .endr
+        addi sp, sp, 32*8
+        sret
+    ",
+    handler = sym trap_handler,
+);
+
+extern "C" {
+    fn strap_entry();
+}
+
+extern "C" fn trap_handler(_frame: &mut TrapFrame) {
+    let scause: usize;
+    unsafe { asm!("csrr {}, scause", out(reg) scause) };
+    match scause {
+        SUPERVISOR_SOFT => {
+            if RETRACT_SSOFT.swap(false, Ordering::Relaxed) {
+                unsafe { sip::clear_ssoft() };
+            }
+            handle_ssoft();
+        }
+        SUPERVISOR_TIMER => {
+            STIMER_AT.store(time::read64(), Ordering::Relaxed);
+            let ret = timer::handle_oneshot();
+            TIMER_ECALL_ERROR.store(ret.error, Ordering::Relaxed);
+            STIMER.fetch_add(1, Ordering::Release);
+        }
+        COUNTER_OVERFLOW => pmu::handle_overflow(),
+        BREAKPOINT => {
+            // 跳过 ebreak，可能是压缩指令
+            skip_instruction();
+            BREAKPOINTS.fetch_add(1, Ordering::Relaxed);
+        }
+        ILLEGAL_INSTRUCTION if PROBING.load(Ordering::Relaxed) => {
+            skip_instruction();
+            ILLEGAL_INSTRUCTIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        _ => {
+            let (sepc, stval): (usize, usize);
+            unsafe { asm!("csrr {}, sepc", "csrr {}, stval", out(reg) sepc, out(reg) stval) };
+            panic!("unexpected trap: scause {scause:#x}, sepc {sepc:#x}, stval {stval:#x}");
+        }
+    }
+}
+
+/// 把 `sepc` 移到陷入指令之后，根据最低两位区分 16 位压缩指令和 32 位指令。
+fn skip_instruction() {
+    let sepc: usize;
+    unsafe { asm!("csrr {}, sepc", out(reg) sepc) };
+    let len = if unsafe { (sepc as *const u16).read() } & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    };
+    unsafe { asm!("csrw sepc, {}", in(reg) sepc + len) };
+}
+
+/// 执行一次 `rdtime`，非法指令异常被跳过，此时返回 `None`。
+pub(crate) fn probe_rdtime() -> Option<u64> {
+    let before = ILLEGAL_INSTRUCTIONS.load(Ordering::Relaxed);
+    let mut value: usize = 0;
+    PROBING.store(true, Ordering::Relaxed);
+    unsafe { asm!("rdtime {}", inout(reg) value) };
+    PROBING.store(false, Ordering::Relaxed);
+    if ILLEGAL_INSTRUCTIONS.load(Ordering::Relaxed) == before {
+        Some(value as u64)
+    } else {
+        None
+    }
+}
+
+/// 处理 S 态软件中断：再读一次 SSIP，已经被清除时视为虚假中断直接返回。
+pub(crate) fn handle_ssoft() {
+    if !sip::read().ssoft() {
+        SPURIOUS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    unsafe { sip::clear_ssoft() };
+    SSOFT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 分别触发 S 态时钟中断和软件中断，确认它们由本内核的 S 态处理函数处理，
+/// 也就是 RustSBI 设置 `mideleg` 把它们委托给了 S 态，而不是在 M 态被吞掉。
+pub(crate) fn test_delegation(env: &Env) -> TestOutcome {
+    require_ext!(TIME, "delegation");
+    require_ext!(SPI, "delegation");
+    let timer = taken(env, &STIMER, || {
+        IRQS.enable(env.hartid, IrqSource::Timer);
+        sbi::set_timer(time::read64());
+    });
+    IRQS.disable(env.hartid, IrqSource::Timer);
+    let soft = taken(env, &SSOFT, || {
+        IRQS.enable(env.hartid, IrqSource::Software);
+        sbi::send_ipi(1 << env.hartid, 0);
+    });
+    IRQS.disable(env.hartid, IrqSource::Software);
+    // 未处理的中断不要留给后面的测试
+    sbi::set_timer(u64::MAX);
+    unsafe { sip::clear_ssoft() };
+
+    let verdict = |ok| if ok { "delegated" } else { "NOT delegated" };
+    diag!("supervisor timer interrupt: {}", verdict(timer));
+    diag!("supervisor software interrupt: {}", verdict(soft));
+    if timer && soft {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail
+    }
+}
+
+/// 打开 `sstatus.SIE`，用 `raise` 触发中断，等待最多 100 毫秒看 `counter` 是否增加。
+fn taken(env: &Env, counter: &AtomicUsize, raise: impl FnOnce()) -> bool {
+    let before = counter.load(Ordering::Relaxed);
+    let watchdog = Watchdog::new(env.board.frequency, 100);
+    raise();
+    unsafe { sstatus::set_sie() };
+    while counter.load(Ordering::Relaxed) == before && !watchdog.expired() {
+        core::hint::spin_loop();
+    }
+    unsafe { sstatus::clear_sie() };
+    counter.load(Ordering::Relaxed) != before
+}
+
+/// 填入通用寄存器的标记值，`xn` 填 `SENTINEL + n`。
+const SENTINEL: usize = 0x5a5a_5a5a_0000_0000;
+
+/// 把除 `sp` 外的所有通用寄存器设为标记值，执行 `ebreak`，返回后把寄存器存入 `out`，
+/// `out[0]` 是 `ebreak` 之前的 `sp`。
+///
+/// # Safety
+///
+/// 裸函数，按调用约定保存并恢复被调用者保存的寄存器。
+#[unsafe(naked)]
+unsafe extern "C" fn clobber_probe(out: &mut [usize; 32]) {
+    naked_asm!(
+        "   addi sp, sp, -18*8
+            sd   ra,  0*8(sp)
+            sd   gp,  1*8(sp)
+            sd   tp,  2*8(sp)
+            sd   s0,  3*8(sp)
+            sd   s1,  4*8(sp)
+        ",
+        ".irp n, 18,19,20,21,22,23,24,25,26,27
+            sd   x\\n, (\\n-13)*8(sp)
+        .endr",
+        "   sd   a0, 15*8(sp)
+            sd   sp,  0*8(a0)
+        ",
+        ".irp n, 1,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+            li   x\\n, {sentinel} + \\n
+        .endr",
+        "   ebreak
+            sd   x31, 16*8(sp)
+            ld   x31, 15*8(sp)
+        ",
+        ".irp n, 1,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30
+            sd   x\\n, \\n*8(x31)
+        .endr",
+        "   sd   sp,  2*8(x31)
+            ld   x30, 16*8(sp)
+            sd   x30, 31*8(x31)
+            ld   ra,  0*8(sp)
+            ld   gp,  1*8(sp)
+            ld   tp,  2*8(sp)
+            ld   s0,  3*8(sp)
+            ld   s1,  4*8(sp)
+        ",
+        ".irp n, 18,19,20,21,22,23,24,25,26,27
+            ld   x\\n, (\\n-13)*8(sp)
+        .endr",
+        "   addi sp, sp, 18*8
+            ret
+        ",
+        sentinel = const SENTINEL,
+    )
+}
+
+/// 在所有通用寄存器都是标记值时触发一次断点异常，确认陷入处理返回后没有寄存器被破坏。
+pub(crate) fn test_trap_clobber(_env: &Env) -> TestOutcome {
+    let mut regs = [0usize; 32];
+    let before = BREAKPOINTS.load(Ordering::Relaxed);
+    unsafe { clobber_probe(&mut regs) };
+    if BREAKPOINTS.load(Ordering::Relaxed) == before {
+        diag!("breakpoint was not handled");
+        return TestOutcome::Fail;
+    }
+    let mut ans = TestOutcome::Pass;
+    if regs[2] != regs[0] {
+        diag!("x2 (sp) clobbered: {:#x}, expected {:#x}", regs[2], regs[0]);
+        ans = TestOutcome::Fail;
+    }
+    for (n, &val) in regs.iter().enumerate().filter(|&(n, _)| n != 0 && n != 2) {
+        if val != SENTINEL + n {
+            diag!("x{n} clobbered: {val:#x}, expected {:#x}", SENTINEL + n);
+            ans = TestOutcome::Fail;
+        }
+    }
+    ans
+}
@@ -0,0 +1,25 @@
+//! 看门狗。
+
+use riscv::register::time;
+
+/// 以 `time` 计时的看门狗，防止测试因为固件不响应而一直等待下去。
+///
+/// 它不会打断正在执行的代码，等待循环需要主动检查 [`Watchdog::expired`]。
+pub(crate) struct Watchdog {
+    deadline: u64,
+}
+
+impl Watchdog {
+    /// 在 `frequency` 频率下 `millis` 毫秒后到期的看门狗。
+    pub fn new(frequency: u64, millis: u64) -> Self {
+        Self {
+            deadline: time::read64() + frequency * millis / 1000,
+        }
+    }
+
+    /// 是否已经到期。
+    #[inline]
+    pub fn expired(&self) -> bool {
+        time::read64() > self.deadline
+    }
+}